@@ -0,0 +1,27 @@
+#!/bin/sh
+# Measures how the throughput of dur scales with the number
+# of actix workers, for the memory and sharded memory backends.
+#
+# USAGE:
+#     sh benches/workers.sh [WORKERS...]
+set -e
+
+PORT=8099
+WORKERS=${*:-1 2 4 8}
+
+cargo build --release --bins --examples
+
+for backend in memory sharded_memory; do
+  for workers in $WORKERS; do
+    ./target/release/dur --backend "$backend" --workers "$workers" \
+      --port "$PORT" --limit 1000000 2>/dev/null &
+    pid=$!
+    sleep 1
+
+    printf "%-16s %2s workers: " "$backend" "$workers"
+    ./target/release/examples/bench "127.0.0.1:$PORT" 64 10
+
+    kill "$pid"
+    wait "$pid" 2>/dev/null || true
+  done
+done
@@ -0,0 +1,97 @@
+// Sends requests to a running dur over keep-alive connections
+// as fast as it can, and reports the throughput.
+//
+// USAGE:
+//     cargo run --release --example bench -- [ADDR] [CONNECTIONS] [SECONDS] [IDS]
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let addr = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| "127.0.0.1:8000".to_owned());
+    let connections: u64 = args.get(2).map_or(64, |v| v.parse().unwrap());
+    let seconds: u64 = args.get(3).map_or(10, |v| v.parse().unwrap());
+    let ids: u64 = args.get(4).map_or(100_000, |v| v.parse().unwrap());
+
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(seconds);
+
+    let handles: Vec<_> = (0..connections)
+        .map(|connection| {
+            let addr = addr.clone();
+            thread::spawn(move || send_requests(&addr, connection, ids, deadline))
+        })
+        .collect();
+
+    let total: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    let elapsed = started.elapsed().as_secs_f64();
+
+    println!(
+        "{} requests in {:.2}s over {} connections, {:.0} requests/s",
+        total,
+        elapsed,
+        connections,
+        total as f64 / elapsed
+    );
+}
+
+fn send_requests(addr: &str, connection: u64, ids: u64, deadline: Instant) -> u64 {
+    let stream = TcpStream::connect(addr).expect("dur is not running");
+    stream.set_nodelay(true).unwrap();
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+
+    // a simple LCG is enough to spread the requests across ids.
+    let mut seed = connection;
+    let mut count = 0;
+
+    while Instant::now() < deadline {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let body = format!("{{\"id\":{}}}", (seed >> 33) % ids);
+
+        write!(
+            writer,
+            "POST /request HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            addr,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        read_response(&mut reader);
+        count += 1;
+    }
+
+    count
+}
+
+fn read_response(reader: &mut BufReader<TcpStream>) {
+    let mut length = 0;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+}
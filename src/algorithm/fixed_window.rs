@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Outcome, Rate};
+
+// Counts requests in fixed windows of `period`,
+// starting from the unix epoch. The count is reset
+// when a new window starts.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FixedWindow {
+    window: u64,
+    count: u32,
+}
+
+impl FixedWindow {
+    pub fn new(timestamp: Duration, rate: &Rate) -> Self {
+        Self {
+            window: window_of(timestamp, rate),
+            count: 0,
+        }
+    }
+
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        let window = window_of(timestamp, rate);
+        if window > self.window {
+            self.window = window;
+            self.count = 0;
+        }
+
+        if self.count.saturating_add(rate.cost) > rate.limit {
+            if rate.cost > rate.limit {
+                return Outcome::deny(None);
+            }
+
+            let period = rate.period.as_nanos().max(1);
+            let left = period - timestamp.as_nanos() % period;
+
+            return Outcome::deny(Some(Duration::from_nanos(left as u64)));
+        }
+
+        self.count += rate.cost;
+
+        Outcome::allow(rate.limit - self.count)
+    }
+
+    // The request is only given back to the window it was
+    // counted in, a later window starts over anyway.
+    pub fn release(&mut self, timestamp: Duration, rate: &Rate) {
+        if window_of(timestamp, rate) == self.window {
+            self.count = self.count.saturating_sub(rate.cost);
+        }
+    }
+}
+
+fn window_of(timestamp: Duration, rate: &Rate) -> u64 {
+    (timestamp.as_nanos() / rate.period.as_nanos().max(1)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_reset_on_new_window() {
+        let rate = Rate::new(Algorithm::FixedWindow, 2, Duration::from_secs(10));
+        let now = Duration::from_secs(1004);
+        let mut window = FixedWindow::new(now, &rate);
+
+        assert_eq!(window.acquire(now, &rate), Outcome::allow(1));
+        assert_eq!(window.acquire(now, &rate), Outcome::allow(0));
+        assert_eq!(
+            window.acquire(now, &rate),
+            Outcome::deny(Some(Duration::from_secs(6)))
+        );
+
+        let next = Duration::from_secs(1010);
+        assert_eq!(window.acquire(next, &rate), Outcome::allow(1));
+    }
+
+    #[test]
+    fn test_cost() {
+        let rate = Rate::new(Algorithm::FixedWindow, 3, Duration::from_secs(10)).with_cost(2);
+        let now = Duration::from_secs(1000);
+        let mut window = FixedWindow::new(now, &rate);
+
+        assert_eq!(window.acquire(now, &rate), Outcome::allow(1));
+        assert_eq!(
+            window.acquire(now, &rate),
+            Outcome::deny(Some(Duration::from_secs(10)))
+        );
+        assert_eq!(window.acquire(now, &rate.with_cost(1)), Outcome::allow(0));
+        assert_eq!(window.acquire(now, &rate.with_cost(4)), Outcome::deny(None));
+    }
+
+    #[test]
+    fn test_release() {
+        let rate = Rate::new(Algorithm::FixedWindow, 2, Duration::from_secs(10));
+        let now = Duration::from_secs(1004);
+        let mut window = FixedWindow::new(now, &rate);
+
+        assert!(window.acquire(now, &rate).allowed);
+        assert!(window.acquire(now, &rate).allowed);
+        window.release(now, &rate);
+        assert_eq!(window.acquire(now, &rate), Outcome::allow(0));
+
+        // a request counted in an earlier window isn't given back.
+        let next = Duration::from_secs(1010);
+        assert_eq!(window.acquire(next, &rate), Outcome::allow(1));
+        window.release(now, &rate);
+        assert_eq!(window.acquire(next, &rate), Outcome::allow(0));
+    }
+}
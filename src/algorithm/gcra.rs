@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Outcome, Rate};
+
+// Generic cell rate algorithm. Requests are expected to
+// arrive once every `period / limit`, and up to `limit`
+// requests can arrive earlier than expected. The only state
+// is the theoretical arrival time of the next request, which
+// also tells exactly when a denied request can be retried.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Gcra {
+    tat: Duration,
+}
+
+impl Gcra {
+    pub fn new(timestamp: Duration) -> Self {
+        Self { tat: timestamp }
+    }
+
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        if rate.limit == 0 || rate.cost > rate.limit {
+            return Outcome::deny(None);
+        }
+
+        // a request takes the emission intervals
+        // of as many requests as it costs.
+        let emission_interval = rate.period / rate.limit;
+        let tat = self.tat.max(timestamp);
+        let new_tat = tat + emission_interval * rate.cost;
+        let allow_at = new_tat.saturating_sub(rate.period);
+
+        if timestamp < allow_at {
+            return Outcome::deny(Some(allow_at - timestamp));
+        }
+
+        self.tat = new_tat;
+
+        Outcome::allow(remaining(timestamp, new_tat, rate, emission_interval))
+    }
+
+    // Moves the theoretical arrival time back by the emission
+    // intervals of the request, but never before the request.
+    pub fn release(&mut self, timestamp: Duration, rate: &Rate) {
+        if rate.limit == 0 {
+            return;
+        }
+
+        let emission_interval = rate.period / rate.limit;
+        self.tat = self
+            .tat
+            .saturating_sub(emission_interval * rate.cost)
+            .max(timestamp);
+    }
+}
+
+// The number of requests that still fit in before
+// the theoretical arrival time leaves the period.
+fn remaining(timestamp: Duration, tat: Duration, rate: &Rate, emission_interval: Duration) -> u32 {
+    let used = tat.saturating_sub(timestamp);
+    let left = rate.period.saturating_sub(used);
+
+    (left.as_nanos() / emission_interval.as_nanos().max(1)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_burst_and_retry_after() {
+        // one request every 100ms, with a burst of 10.
+        let rate = Rate::new(Algorithm::Gcra, 10, Duration::from_secs(1));
+        let now = Duration::from_secs(1000);
+        let mut gcra = Gcra::new(now);
+
+        for remaining in (0..10).rev() {
+            let outcome = gcra.acquire(now, &rate);
+            assert!(outcome.allowed);
+            assert_eq!(outcome.remaining, remaining);
+        }
+
+        let outcome = gcra.acquire(now, &rate);
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.retry_after, Some(Duration::from_millis(100)));
+
+        let outcome = gcra.acquire(now + Duration::from_millis(40), &rate);
+        assert_eq!(outcome.retry_after, Some(Duration::from_millis(60)));
+
+        assert!(
+            gcra.acquire(now + Duration::from_millis(100), &rate)
+                .allowed
+        );
+        assert!(
+            !gcra
+                .acquire(now + Duration::from_millis(100), &rate)
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_steady_rate() {
+        let rate = Rate::new(Algorithm::Gcra, 2, Duration::from_secs(2));
+        let now = Duration::from_secs(1000);
+        let mut gcra = Gcra::new(now);
+
+        for second in 0..10 {
+            assert!(
+                gcra.acquire(now + Duration::from_secs(second), &rate)
+                    .allowed
+            );
+        }
+    }
+
+    #[test]
+    fn test_cost() {
+        // a request every second.
+        let rate = Rate::new(Algorithm::Gcra, 4, Duration::from_secs(4));
+        let now = Duration::from_secs(1000);
+        let mut gcra = Gcra::new(now);
+
+        assert_eq!(gcra.acquire(now, &rate.with_cost(3)), Outcome::allow(1));
+        assert_eq!(
+            gcra.acquire(now, &rate.with_cost(2)),
+            Outcome::deny(Some(Duration::from_secs(1)))
+        );
+        assert_eq!(gcra.acquire(now, &rate.with_cost(5)), Outcome::deny(None));
+    }
+
+    #[test]
+    fn test_release() {
+        let rate = Rate::new(Algorithm::Gcra, 10, Duration::from_secs(1));
+        let now = Duration::from_secs(1000);
+        let mut gcra = Gcra::new(now);
+
+        for _ in 0..10 {
+            assert!(gcra.acquire(now, &rate).allowed);
+        }
+        gcra.release(now, &rate);
+        assert_eq!(gcra.acquire(now, &rate), Outcome::allow(0));
+
+        // the theoretical arrival time never goes before the request.
+        let mut gcra = Gcra::new(now);
+        gcra.release(now, &rate);
+        assert_eq!(gcra.acquire(now, &rate), Outcome::allow(9));
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Outcome, Rate};
+
+// A queue that leaks `limit` requests per `period`.
+// Requests over the rate are not rejected, they are
+// told how long to wait until their turn comes, and only
+// rejected once `max_queue` requests are already waiting.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LeakyBucket {
+    // The earliest time the next request can be processed.
+    next: Duration,
+}
+
+impl LeakyBucket {
+    pub fn new(timestamp: Duration) -> Self {
+        Self { next: timestamp }
+    }
+
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        // a request takes as many places in the queue as it costs.
+        if rate.limit == 0 || rate.cost > rate.max_queue + 1 {
+            return Outcome::deny(None);
+        }
+
+        let interval = rate.period / rate.limit;
+        let turn = self.next.max(timestamp);
+        let delay = turn - timestamp;
+        let queued = queue_length(delay, interval) + rate.cost - 1;
+
+        if queued > rate.max_queue {
+            let max_delay = interval * (rate.max_queue + 1 - rate.cost);
+            return Outcome::deny(Some(delay - max_delay));
+        }
+
+        self.next = turn + interval * rate.cost;
+
+        Outcome {
+            delay: Some(delay).filter(|d| !d.is_zero()),
+            ..Outcome::allow(rate.max_queue - queued)
+        }
+    }
+
+    // Gives the places of the request in the queue back.
+    pub fn release(&mut self, timestamp: Duration, rate: &Rate) {
+        if rate.limit == 0 {
+            return;
+        }
+
+        let interval = rate.period / rate.limit;
+        self.next = self
+            .next
+            .saturating_sub(interval * rate.cost)
+            .max(timestamp);
+    }
+}
+
+// The number of requests waiting in front of a
+// request that has to wait for the given delay.
+fn queue_length(delay: Duration, interval: Duration) -> u32 {
+    let interval = interval.as_nanos().max(1);
+
+    delay.as_nanos().div_ceil(interval) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_requests_are_delayed() {
+        // one request every 100ms, with room for 2 in the queue.
+        let rate = Rate::new(Algorithm::LeakyBucket, 10, Duration::from_secs(1)).with_max_queue(2);
+        let now = Duration::from_secs(1000);
+        let mut bucket = LeakyBucket::new(now);
+
+        let outcome = bucket.acquire(now, &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, None);
+
+        let outcome = bucket.acquire(now, &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, Some(Duration::from_millis(100)));
+
+        let outcome = bucket.acquire(now, &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, Some(Duration::from_millis(200)));
+        assert_eq!(outcome.remaining, 0);
+
+        let outcome = bucket.acquire(now, &rate);
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.retry_after, Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_queue_drains() {
+        let rate = Rate::new(Algorithm::LeakyBucket, 10, Duration::from_secs(1)).with_max_queue(1);
+        let now = Duration::from_secs(1000);
+        let mut bucket = LeakyBucket::new(now);
+
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert!(!bucket.acquire(now, &rate).allowed);
+
+        let later = now + Duration::from_millis(150);
+        let outcome = bucket.acquire(later, &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_cost() {
+        let rate = Rate::new(Algorithm::LeakyBucket, 10, Duration::from_secs(1))
+            .with_max_queue(2)
+            .with_cost(2);
+        let now = Duration::from_secs(1000);
+        let mut bucket = LeakyBucket::new(now);
+
+        assert_eq!(bucket.acquire(now, &rate), Outcome::allow(1));
+
+        // the request takes two places behind the first one.
+        let outcome = bucket.acquire(now, &rate);
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.retry_after, Some(Duration::from_millis(100)));
+
+        let later = now + Duration::from_millis(100);
+        let outcome = bucket.acquire(later, &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, Some(Duration::from_millis(100)));
+        assert_eq!(bucket.acquire(now, &rate.with_cost(4)), Outcome::deny(None));
+    }
+
+    #[test]
+    fn test_release() {
+        let rate = Rate::new(Algorithm::LeakyBucket, 10, Duration::from_secs(1)).with_max_queue(2);
+        let now = Duration::from_secs(1000);
+        let mut bucket = LeakyBucket::new(now);
+
+        for _ in 0..3 {
+            assert!(bucket.acquire(now, &rate).allowed);
+        }
+        bucket.release(now, &rate);
+
+        let outcome = bucket.acquire(now, &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, Some(Duration::from_millis(200)));
+        assert!(!bucket.acquire(now, &rate).allowed);
+    }
+}
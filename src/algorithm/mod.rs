@@ -0,0 +1,257 @@
+mod fixed_window;
+mod gcra;
+mod leaky_bucket;
+mod sliding_window_counter;
+mod sliding_window_log;
+mod token_bucket;
+
+pub use fixed_window::FixedWindow;
+pub use gcra::Gcra;
+pub use leaky_bucket::LeakyBucket;
+pub use sliding_window_counter::SlidingWindowCounter;
+pub use sliding_window_log::SlidingWindowLog;
+pub use token_bucket::TokenBucket;
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+// The algorithm dur uses to decide whether
+// a request should be allowed or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    #[default]
+    SlidingWindowLog,
+    TokenBucket,
+    SlidingWindowCounter,
+    Gcra,
+    LeakyBucket,
+    FixedWindow,
+}
+
+impl Algorithm {
+    pub const VARIANTS: &'static [&'static str] = &[
+        "sliding_window_log",
+        "token_bucket",
+        "sliding_window_counter",
+        "gcra",
+        "leaky_bucket",
+        "fixed_window",
+    ];
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sliding_window_log" => Ok(Algorithm::SlidingWindowLog),
+            "token_bucket" => Ok(Algorithm::TokenBucket),
+            "sliding_window_counter" => Ok(Algorithm::SlidingWindowCounter),
+            "gcra" => Ok(Algorithm::Gcra),
+            "leaky_bucket" => Ok(Algorithm::LeakyBucket),
+            "fixed_window" => Ok(Algorithm::FixedWindow),
+            _ => Err(format!("unknown algorithm: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Algorithm::SlidingWindowLog => "sliding_window_log",
+            Algorithm::TokenBucket => "token_bucket",
+            Algorithm::SlidingWindowCounter => "sliding_window_counter",
+            Algorithm::Gcra => "gcra",
+            Algorithm::LeakyBucket => "leaky_bucket",
+            Algorithm::FixedWindow => "fixed_window",
+        };
+
+        f.write_str(name)
+    }
+}
+
+// A limit of `limit` requests per `period`,
+// enforced with the given algorithm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub algorithm: Algorithm,
+    pub limit: u32,
+    pub period: Duration,
+    // The maximum number of requests that can wait in the
+    // queue of a leaky bucket, defaults to the limit.
+    pub max_queue: u32,
+    // How many of the `limit` requests the acquired
+    // request counts as, a single one by default.
+    pub cost: u32,
+}
+
+impl Rate {
+    pub fn new(algorithm: Algorithm, limit: u32, period: Duration) -> Self {
+        Self {
+            algorithm,
+            limit,
+            period,
+            max_queue: limit,
+            cost: 1,
+        }
+    }
+
+    pub fn with_max_queue(mut self, max_queue: u32) -> Self {
+        self.max_queue = max_queue;
+        self
+    }
+
+    pub fn with_cost(mut self, cost: u32) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    // The time after which a state no longer holds any
+    // information, a new state is the same as an expired one.
+    pub fn expiry(&self) -> Duration {
+        let queue = (self.period / self.limit.max(1)).saturating_mul(self.max_queue);
+
+        self.period
+            .saturating_mul(2)
+            .saturating_add(queue)
+            .saturating_add(Duration::from_secs(1))
+    }
+}
+
+// The result of a single request against a rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    pub allowed: bool,
+    pub remaining: u32,
+    // How long a denied request should wait before it
+    // is retried, if the algorithm can tell.
+    pub retry_after: Option<Duration>,
+    // How long an allowed request should wait before it
+    // is processed, when the traffic is being shaped.
+    pub delay: Option<Duration>,
+    // Why the request was denied, or let
+    // through without being limited.
+    pub reason: Option<Reason>,
+}
+
+impl Outcome {
+    pub fn allow(remaining: u32) -> Self {
+        Self {
+            allowed: true,
+            remaining,
+            retry_after: None,
+            delay: None,
+            reason: None,
+        }
+    }
+
+    pub fn deny(retry_after: Option<Duration>) -> Self {
+        Self {
+            allowed: false,
+            remaining: 0,
+            retry_after,
+            delay: None,
+            reason: Some(Reason::Limit),
+        }
+    }
+
+    // Sets the reason of the outcome, if it is denied.
+    pub fn because(mut self, reason: Reason) -> Self {
+        if !self.allowed {
+            self.reason = Some(reason);
+        }
+
+        self
+    }
+}
+
+// The reason a request is denied for, or let
+// through without being limited for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    // The id sent too many requests.
+    Limit,
+    // The id sent too many requests from a limited ip address.
+    IpAddressLimit,
+    // The id sent too many requests to a limited path.
+    PathLimit,
+    // The id sent requests from too many ip addresses.
+    UniqueIpAddresses,
+    // The id sent too many requests that matched a rule.
+    Rule,
+    // The id used up one of its quotas.
+    Quota,
+    // The id has too many requests in flight.
+    Concurrency,
+    // The id is banned for being denied too many times.
+    Banned,
+    // The id, the ip address or the path is on the allow list.
+    Allowlisted,
+    // The id, the ip address or the path is on the deny list.
+    Denylisted,
+}
+
+// The state that is stored for a single key,
+// depending on the algorithm of the rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum State {
+    SlidingWindowLog(SlidingWindowLog),
+    TokenBucket(TokenBucket),
+    SlidingWindowCounter(SlidingWindowCounter),
+    Gcra(Gcra),
+    LeakyBucket(LeakyBucket),
+    FixedWindow(FixedWindow),
+}
+
+impl State {
+    pub fn new(timestamp: Duration, rate: &Rate) -> Self {
+        match rate.algorithm {
+            Algorithm::SlidingWindowCounter => {
+                State::SlidingWindowCounter(SlidingWindowCounter::new(timestamp, rate))
+            }
+            Algorithm::TokenBucket => State::TokenBucket(TokenBucket::new(timestamp, rate)),
+            Algorithm::Gcra => State::Gcra(Gcra::new(timestamp)),
+            Algorithm::LeakyBucket => State::LeakyBucket(LeakyBucket::new(timestamp)),
+            Algorithm::FixedWindow => State::FixedWindow(FixedWindow::new(timestamp, rate)),
+            Algorithm::SlidingWindowLog => State::SlidingWindowLog(SlidingWindowLog::new()),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            State::SlidingWindowLog(_) => Algorithm::SlidingWindowLog,
+            State::TokenBucket(_) => Algorithm::TokenBucket,
+            State::SlidingWindowCounter(_) => Algorithm::SlidingWindowCounter,
+            State::Gcra(_) => Algorithm::Gcra,
+            State::LeakyBucket(_) => Algorithm::LeakyBucket,
+            State::FixedWindow(_) => Algorithm::FixedWindow,
+        }
+    }
+
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        match self {
+            State::SlidingWindowLog(log) => log.acquire(timestamp, rate),
+            State::TokenBucket(bucket) => bucket.acquire(timestamp, rate),
+            State::SlidingWindowCounter(counter) => counter.acquire(timestamp, rate),
+            State::Gcra(gcra) => gcra.acquire(timestamp, rate),
+            State::LeakyBucket(bucket) => bucket.acquire(timestamp, rate),
+            State::FixedWindow(window) => window.acquire(timestamp, rate),
+        }
+    }
+
+    // Gives back a request `acquire` allowed at the given
+    // timestamp, once it's denied by another limit.
+    pub fn release(&mut self, timestamp: Duration, rate: &Rate) {
+        match self {
+            State::SlidingWindowLog(log) => log.release(timestamp, rate),
+            State::TokenBucket(bucket) => bucket.release(rate),
+            State::SlidingWindowCounter(counter) => counter.release(timestamp, rate),
+            State::Gcra(gcra) => gcra.release(timestamp, rate),
+            State::LeakyBucket(bucket) => bucket.release(timestamp, rate),
+            State::FixedWindow(window) => window.release(timestamp, rate),
+        }
+    }
+}
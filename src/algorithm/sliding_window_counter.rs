@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Outcome, Rate};
+
+// Approximates a sliding window with two fixed windows.
+// Only the counts of the current and the previous window
+// are kept, and the previous count is weighted by how much
+// of the previous window still overlaps the sliding window.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SlidingWindowCounter {
+    window: u64,
+    current: u32,
+    previous: u32,
+}
+
+impl SlidingWindowCounter {
+    pub fn new(timestamp: Duration, rate: &Rate) -> Self {
+        Self {
+            window: window_of(timestamp, rate),
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    fn advance(&mut self, timestamp: Duration, rate: &Rate) {
+        let window = window_of(timestamp, rate);
+
+        if window == self.window + 1 {
+            self.previous = self.current;
+            self.current = 0;
+        } else if window > self.window {
+            self.previous = 0;
+            self.current = 0;
+        } else {
+            return;
+        }
+
+        self.window = window;
+    }
+
+    // The weighted number of requests inside the sliding window
+    // that ends at the given timestamp.
+    fn estimate(&self, timestamp: Duration, rate: &Rate) -> f64 {
+        let period = rate.period.as_nanos().max(1);
+        let elapsed = (timestamp.as_nanos() % period) as f64 / period as f64;
+
+        self.previous as f64 * (1.0 - elapsed) + self.current as f64
+    }
+
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        self.advance(timestamp, rate);
+
+        if self.estimate(timestamp, rate) + rate.cost as f64 > rate.limit as f64 {
+            return Outcome::deny(None);
+        }
+
+        self.current += rate.cost;
+        let used = self.estimate(timestamp, rate).ceil() as u32;
+
+        Outcome::allow(rate.limit.saturating_sub(used))
+    }
+
+    // The request is given back to the window it was counted
+    // in, which may have become the previous one since.
+    pub fn release(&mut self, timestamp: Duration, rate: &Rate) {
+        let window = window_of(timestamp, rate);
+
+        if window == self.window {
+            self.current = self.current.saturating_sub(rate.cost);
+        } else if window + 1 == self.window {
+            self.previous = self.previous.saturating_sub(rate.cost);
+        }
+    }
+}
+
+fn window_of(timestamp: Duration, rate: &Rate) -> u64 {
+    (timestamp.as_nanos() / rate.period.as_nanos().max(1)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_limit_inside_a_window() {
+        let rate = Rate::new(Algorithm::SlidingWindowCounter, 3, Duration::from_secs(10));
+        let now = Duration::from_secs(1000);
+        let mut counter = SlidingWindowCounter::new(now, &rate);
+
+        assert_eq!(counter.acquire(now, &rate), Outcome::allow(2));
+        assert!(counter.acquire(now, &rate).allowed);
+        assert!(counter.acquire(now, &rate).allowed);
+        assert_eq!(counter.acquire(now, &rate), Outcome::deny(None));
+    }
+
+    #[test]
+    fn test_previous_window_is_weighted() {
+        let rate = Rate::new(Algorithm::SlidingWindowCounter, 4, Duration::from_secs(10));
+        let now = Duration::from_secs(1000);
+        let mut counter = SlidingWindowCounter::new(now, &rate);
+
+        for _ in 0..4 {
+            assert!(counter.acquire(now, &rate).allowed);
+        }
+
+        // a quarter into the next window, three quarters of the
+        // previous window still count, which leaves room for one.
+        let later = now + Duration::from_millis(12500);
+        assert!(counter.acquire(later, &rate).allowed);
+        assert!(!counter.acquire(later, &rate).allowed);
+
+        // two windows later nothing is left of the old counts.
+        let much_later = now + Duration::from_secs(30);
+        assert_eq!(counter.acquire(much_later, &rate).remaining, 3);
+    }
+
+    #[test]
+    fn test_cost() {
+        let rate = Rate::new(Algorithm::SlidingWindowCounter, 3, Duration::from_secs(10));
+        let now = Duration::from_secs(1000);
+        let mut counter = SlidingWindowCounter::new(now, &rate);
+
+        assert_eq!(counter.acquire(now, &rate.with_cost(2)), Outcome::allow(1));
+        assert!(!counter.acquire(now, &rate.with_cost(2)).allowed);
+        assert_eq!(counter.acquire(now, &rate), Outcome::allow(0));
+    }
+
+    #[test]
+    fn test_release() {
+        let rate = Rate::new(Algorithm::SlidingWindowCounter, 3, Duration::from_secs(10));
+        let now = Duration::from_secs(1000);
+        let mut counter = SlidingWindowCounter::new(now, &rate);
+
+        for _ in 0..3 {
+            assert!(counter.acquire(now, &rate).allowed);
+        }
+        counter.release(now, &rate);
+        assert_eq!(counter.acquire(now, &rate), Outcome::allow(0));
+
+        // the request is given back to the previous window.
+        let next = Duration::from_secs(1010);
+        counter.advance(next, &rate);
+        counter.release(now, &rate);
+        assert_eq!(counter.previous, 2);
+    }
+}
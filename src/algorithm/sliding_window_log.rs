@@ -0,0 +1,139 @@
+use std::{collections::VecDeque, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Outcome, Rate};
+
+// Keeps the timestamps of the allowed requests inside the
+// last `period`, along with what they cost. A request is
+// allowed while its cost still fits under `limit`, and a
+// denied request can be retried once enough of the oldest
+// ones leave the window.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlidingWindowLog {
+    log: VecDeque<(Duration, u32)>,
+}
+
+impl SlidingWindowLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        while let Some(&(oldest, _)) = self.log.front() {
+            if oldest + rate.period > timestamp {
+                break;
+            }
+            self.log.pop_front();
+        }
+
+        let used: u64 = self.log.iter().map(|(_, cost)| *cost as u64).sum();
+        if used + rate.cost as u64 > rate.limit as u64 {
+            let retry_after = if rate.cost <= rate.limit {
+                self.retry_after(used + rate.cost as u64 - rate.limit as u64, timestamp, rate)
+            } else {
+                None
+            };
+
+            return Outcome::deny(retry_after);
+        }
+
+        self.log.push_back((timestamp, rate.cost));
+
+        Outcome::allow(rate.limit - (used as u32 + rate.cost))
+    }
+
+    // How long until the oldest requests that cost at least
+    // `leaving` together are out of the window.
+    fn retry_after(&self, leaving: u64, timestamp: Duration, rate: &Rate) -> Option<Duration> {
+        let mut left = 0;
+        self.log
+            .iter()
+            .find(|(_, cost)| {
+                left += *cost as u64;
+                left >= leaving
+            })
+            .map(|(oldest, _)| (*oldest + rate.period).saturating_sub(timestamp))
+    }
+
+    // Drops the entry the request was logged with.
+    pub fn release(&mut self, timestamp: Duration, rate: &Rate) {
+        let logged = (timestamp, rate.cost);
+        if let Some(position) = self.log.iter().rposition(|entry| *entry == logged) {
+            self.log.remove(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_limit_inside_a_window() {
+        let rate = Rate::new(Algorithm::SlidingWindowLog, 2, Duration::from_secs(10));
+        let now = Duration::from_secs(1000);
+        let mut log = SlidingWindowLog::new();
+
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(1));
+        assert_eq!(
+            log.acquire(now + Duration::from_secs(4), &rate),
+            Outcome::allow(0)
+        );
+        assert_eq!(
+            log.acquire(now + Duration::from_secs(6), &rate),
+            Outcome::deny(Some(Duration::from_secs(4)))
+        );
+
+        // the first request left the window.
+        assert_eq!(
+            log.acquire(now + Duration::from_secs(10), &rate),
+            Outcome::allow(0)
+        );
+    }
+
+    #[test]
+    fn test_cost() {
+        let rate = Rate::new(Algorithm::SlidingWindowLog, 3, Duration::from_secs(10));
+        let now = Duration::from_secs(1000);
+        let mut log = SlidingWindowLog::new();
+
+        assert_eq!(log.acquire(now, &rate.with_cost(2)), Outcome::allow(1));
+
+        // a unit of the first request has to leave the window.
+        let later = now + Duration::from_secs(4);
+        assert_eq!(
+            log.acquire(later, &rate.with_cost(2)),
+            Outcome::deny(Some(Duration::from_secs(6)))
+        );
+        assert_eq!(log.acquire(later, &rate), Outcome::allow(0));
+        assert_eq!(
+            log.acquire(later + Duration::from_secs(1), &rate.with_cost(3)),
+            Outcome::deny(Some(Duration::from_secs(9)))
+        );
+        assert_eq!(log.acquire(later, &rate.with_cost(4)), Outcome::deny(None));
+    }
+
+    #[test]
+    fn test_release() {
+        let rate = Rate::new(Algorithm::SlidingWindowLog, 3, Duration::from_secs(10)).with_cost(2);
+        let now = Duration::from_secs(1000);
+        let mut log = SlidingWindowLog::new();
+
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(1));
+        log.release(now, &rate);
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(1));
+        assert!(!log.acquire(now, &rate).allowed);
+
+        // only one of two alike requests is dropped.
+        let rate = rate.with_cost(1);
+        let mut log = SlidingWindowLog::new();
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(2));
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(1));
+        log.release(now, &rate);
+        log.release(now, &rate.with_cost(3));
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(1));
+        assert_eq!(log.acquire(now, &rate), Outcome::allow(0));
+    }
+}
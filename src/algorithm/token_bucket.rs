@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Outcome, Rate};
+
+// A bucket that holds up to `limit` tokens and is
+// refilled continuously with `limit` tokens per `period`.
+// Every request takes as many tokens as it costs, so clients
+// can burst up to the capacity and are then throttled down
+// to the refill rate.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TokenBucket {
+    tokens: f64,
+    last_refill: Duration,
+}
+
+impl TokenBucket {
+    // Creates a full bucket.
+    pub fn new(timestamp: Duration, rate: &Rate) -> Self {
+        Self {
+            tokens: rate.limit as f64,
+            last_refill: timestamp,
+        }
+    }
+
+    fn refill(&mut self, timestamp: Duration, rate: &Rate) {
+        if timestamp <= self.last_refill {
+            return;
+        }
+
+        let elapsed = (timestamp - self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_rate(rate)).min(rate.limit as f64);
+        self.last_refill = timestamp;
+    }
+
+    // Refills the bucket up to the given timestamp and takes
+    // the tokens of the request from it if there are enough.
+    pub fn acquire(&mut self, timestamp: Duration, rate: &Rate) -> Outcome {
+        self.refill(timestamp, rate);
+
+        let cost = rate.cost as f64;
+        if self.tokens < cost {
+            // a request that costs more than the
+            // capacity can never be allowed.
+            if rate.cost > rate.limit {
+                return Outcome::deny(None);
+            }
+
+            // the time it takes for the missing
+            // tokens to be refilled.
+            let retry_after = (cost - self.tokens) / refill_rate(rate);
+
+            return Outcome::deny(Duration::try_from_secs_f64(retry_after).ok());
+        }
+
+        self.tokens -= cost;
+
+        Outcome::allow(self.remaining())
+    }
+
+    // Puts the tokens of the request back in the bucket.
+    pub fn release(&mut self, rate: &Rate) {
+        self.tokens = (self.tokens + rate.cost as f64).min(rate.limit as f64);
+    }
+
+    // The number of whole tokens left in the bucket.
+    pub fn remaining(&self) -> u32 {
+        self.tokens.floor() as u32
+    }
+}
+
+// Tokens that are added to the bucket per second.
+fn refill_rate(rate: &Rate) -> f64 {
+    rate.limit as f64 / rate.period.as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_burst_up_to_capacity() {
+        let now = Duration::from_secs(1000);
+        let rate = Rate::new(Algorithm::TokenBucket, 3, Duration::from_secs(3));
+        let mut bucket = TokenBucket::new(now, &rate);
+
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert_eq!(bucket.remaining(), 0);
+
+        let outcome = bucket.acquire(now, &rate);
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.retry_after, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_refill() {
+        let now = Duration::from_secs(1000);
+        // half a token per second.
+        let rate = Rate::new(Algorithm::TokenBucket, 2, Duration::from_secs(4));
+        let mut bucket = TokenBucket::new(now, &rate);
+
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert!(bucket.acquire(now, &rate).allowed);
+        assert!(!bucket.acquire(now + Duration::from_secs(1), &rate).allowed);
+
+        // two more seconds add one token.
+        assert!(bucket.acquire(now + Duration::from_secs(3), &rate).allowed);
+        assert!(!bucket.acquire(now + Duration::from_secs(3), &rate).allowed);
+
+        // refilling never goes above the capacity.
+        let outcome = bucket.acquire(now + Duration::from_secs(100), &rate);
+        assert!(outcome.allowed);
+        assert_eq!(outcome.remaining, 1);
+    }
+
+    #[test]
+    fn test_cost() {
+        let now = Duration::from_secs(1000);
+        // a token per second.
+        let rate = Rate::new(Algorithm::TokenBucket, 4, Duration::from_secs(4)).with_cost(3);
+        let mut bucket = TokenBucket::new(now, &rate);
+
+        assert_eq!(bucket.acquire(now, &rate), Outcome::allow(1));
+        assert_eq!(
+            bucket.acquire(now, &rate),
+            Outcome::deny(Some(Duration::from_secs(2)))
+        );
+        assert_eq!(bucket.acquire(now, &rate.with_cost(5)), Outcome::deny(None));
+    }
+
+    #[test]
+    fn test_release() {
+        let now = Duration::from_secs(1000);
+        let rate = Rate::new(Algorithm::TokenBucket, 3, Duration::from_secs(3)).with_cost(2);
+        let mut bucket = TokenBucket::new(now, &rate);
+
+        assert!(bucket.acquire(now, &rate).allowed);
+        bucket.release(&rate);
+        assert_eq!(bucket.remaining(), 3);
+
+        // the bucket never holds more than its capacity.
+        bucket.release(&rate);
+        assert_eq!(bucket.remaining(), 3);
+    }
+}
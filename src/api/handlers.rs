@@ -1,9 +1,23 @@
-use std::{net::Ipv4Addr, str::FromStr, sync::Mutex};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    time::Duration,
+};
 
-use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::{delete, error::BlockingError, get, post, put, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::{IpAndPath, Memory};
+use crate::{
+    algorithm::{Outcome, Reason},
+    config::KeyTemplate,
+    dur::{Decision, LeaseOutcome, LeaseUsage, QuotaUsage, RuleOutcome},
+    helpers::KeyHasher,
+    Backend, Ban, Dur, IpAndPath, Override,
+};
 
 #[derive(Serialize)]
 struct Health<T>
@@ -23,23 +37,141 @@ pub async fn get_health() -> HttpResponse {
 #[derive(Serialize)]
 struct LimitResponse {
     allowed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delay_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<Reason>,
+    // The rules the request matched, and whether it
+    // passed or failed each of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<RuleResponse>,
+    // The quotas of the id, and what is left of each of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    quotas: Vec<RuleResponse>,
     metadata: Metadata,
 }
 
+#[derive(Serialize)]
+struct RuleResponse {
+    name: String,
+    allowed: bool,
+    remaining: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_ms: Option<u128>,
+}
+
+impl From<RuleOutcome> for RuleResponse {
+    fn from(rule: RuleOutcome) -> Self {
+        let outcome = rule.outcome;
+
+        Self {
+            name: rule.name,
+            allowed: outcome.allowed,
+            remaining: outcome.remaining,
+            retry_after_ms: outcome
+                .retry_after
+                .filter(|_| !outcome.allowed)
+                .map(|d| d.as_millis()),
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct Metadata {
-    id: u64,
+    id: Option<Id>,
+    // The key built from the key template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tier: Option<String>,
     x_ratelimit_remaning: i32,
     x_ratelimit_limit: u32,
     path: Option<String>,
     ip: Option<String>,
+    // The limited path pattern the request matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Request {
-    id: u64,
+    id: Option<Id>,
     path: Option<String>,
     ip: Option<String>,
+    method: Option<String>,
+    // The tier of the id, instead of the one it's listed in.
+    tier: Option<String>,
+    // How many requests the request counts as, the cost
+    // of the rules it matches or one by default.
+    cost: Option<u32>,
+    // The other fields of the request, the key
+    // template can be built from any of them.
+    #[serde(flatten)]
+    fields: HashMap<String, Value>,
+}
+
+// Ids are either numbers, or strings such as api keys.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum Id {
+    Number(u64),
+    String(String),
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Id::Number(id) => write!(f, "{}", id),
+            Id::String(id) => f.write_str(id),
+        }
+    }
+}
+
+impl Request {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "id" => self.id.as_ref().map(ToString::to_string),
+            "path" => self.path.clone(),
+            "ip" => self.ip.clone(),
+            "method" => self.method.clone(),
+            "tier" => self.tier.clone(),
+            "cost" => self.cost.map(|cost| cost.to_string()),
+            _ => match self.fields.get(name)? {
+                Value::String(value) => Some(value.clone()),
+                Value::Number(value) => Some(value.to_string()),
+                Value::Bool(value) => Some(value.to_string()),
+                _ => None,
+            },
+        }
+    }
+
+    fn ip_addr(&self) -> Result<Option<IpAddr>, String> {
+        match self.ip {
+            Some(ref ip) => IpAddr::from_str(ip)
+                .map(Some)
+                .map_err(|_| format!("invalid ip address: {}", ip)),
+            None => Ok(None),
+        }
+    }
+
+    // The key of the request, built from the template if
+    // there is one, and the id it's stored under in the backend.
+    fn key(
+        &self,
+        template: Option<&KeyTemplate>,
+        hasher: &KeyHasher,
+    ) -> Result<(String, u64), String> {
+        let key = match (template, &self.id) {
+            (Some(template), _) => template.render(|name| self.field(name))?,
+            (None, Some(id)) => id.to_string(),
+            (None, None) => return Err("missing field: id".to_owned()),
+        };
+        let id = hasher.id_of(&key);
+
+        Ok((key, id))
+    }
 }
 
 #[derive(Serialize)]
@@ -50,38 +182,546 @@ pub struct BadRequest {
 #[post("/request")]
 pub async fn new_request(
     payload: web::Json<Request>,
-    data: web::Data<Mutex<crate::Dur<Memory>>>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> impl Responder {
+    limit(payload, data, false).await
+}
+
+// Responds the same way as a request, without
+// counting the request against any of the limits.
+#[post("/check")]
+pub async fn check_request(
+    payload: web::Json<Request>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> impl Responder {
+    limit(payload, data, true).await
+}
+
+async fn limit(
+    payload: web::Json<Request>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+    dry_run: bool,
 ) -> impl Responder {
-    let mut _data = data.lock().unwrap();
-
-    let ip_addr: Option<Ipv4Addr> = match payload.ip {
-        None => None,
-        Some(ref v) => match Ipv4Addr::from_str(v) {
-            Ok(ip) => Some(ip),
-            Err(_) => {
-                return HttpResponse::BadRequest()
-                    .json(BadRequest {
-                        error: format!("invalid ip address: {}", v),
-                    })
-                    .with_header("X-Ratelimit-Limit", _data.config.limit() as usize)
+    let ip_addr = match payload.ip_addr() {
+        Ok(ip_addr) => ip_addr,
+        Err(error) => {
+            return HttpResponse::BadRequest()
+                .json(BadRequest { error })
+                .with_header("X-Ratelimit-Limit", data.limit() as usize)
+        }
+    };
+
+    let (key, id) = match payload.key(data.config.key(), &data.config.hasher()) {
+        Ok(key) => key,
+        Err(error) => {
+            return HttpResponse::BadRequest()
+                .json(BadRequest { error })
+                .with_header("X-Ratelimit-Limit", data.limit() as usize)
+        }
+    };
+    let tier = match payload.tier {
+        Some(ref name) if data.config.tier(name).is_none() => {
+            return HttpResponse::BadRequest()
+                .json(BadRequest {
+                    error: format!("unknown tier: {}", name),
+                })
+                .with_header("X-Ratelimit-Limit", data.limit() as usize)
+        }
+        Some(ref name) => Some(name.clone()),
+        None => data.config.tier_of(&key).map(ToOwned::to_owned),
+    };
+    if payload.cost == Some(0) {
+        return HttpResponse::BadRequest()
+            .json(BadRequest {
+                error: "cost must be at least 1".to_owned(),
+            })
+            .with_header("X-Ratelimit-Limit", data.limit() as usize);
+    }
+    // a request that costs more than the limit of the id
+    // could never be allowed.
+    if let Some(cost) = payload.cost.filter(|cost| *cost > 1) {
+        let name = tier.clone();
+        let limit = match admin(data.clone(), move |dur| {
+            dur.limit_of_id(id, name.as_deref())
+        })
+        .await
+        {
+            Ok(limit) => limit,
+            Err(response) => {
+                return response.with_header("X-Ratelimit-Limit", data.limit() as usize)
             }
-        },
+        };
+        if cost > limit {
+            return HttpResponse::BadRequest()
+                .json(BadRequest {
+                    error: format!("cost must be at most {}", limit),
+                })
+                .with_header("X-Ratelimit-Limit", limit as usize);
+        }
+    }
+    let ip_and_path = IpAndPath::new(ip_addr, payload.path.clone())
+        .with_method(payload.method.clone())
+        .with_tier(tier.clone())
+        .with_cost(payload.cost);
+
+    let Decision {
+        outcome,
+        rules,
+        matched_path,
+        limit,
+        quotas,
+    } = if data.is_blocking() {
+        let dur = data.clone();
+        web::block(move || Ok::<_, ()>(decide(&dur, id, ip_and_path, dry_run)))
+            .await
+            .unwrap_or_else(|_| Outcome::deny(None).into())
+    } else {
+        decide(&data, id, ip_and_path, dry_run)
     };
+    // the override or the tier of the id is used when
+    // the request was decided before its limit was known.
+    let limit = limit.unwrap_or_else(|| data.limit_of(tier.as_deref()));
+    let remaning_requests = outcome.remaining as i32;
+    let retry_after = outcome.retry_after.filter(|_| !outcome.allowed);
 
-    let request = _data.request(payload.id, IpAndPath::new(ip_addr, payload.path.clone()));
-    let remaning_requests: i32 = _data.config.limit() as i32 - request.1 as i32;
+    let mut response = HttpResponse::Ok();
+    if let Some(retry_after) = retry_after {
+        response.header("Retry-After", retry_after_secs(retry_after));
+    }
 
-    HttpResponse::Ok()
+    response
         .json(LimitResponse {
-            allowed: request.0,
+            allowed: outcome.allowed,
+            retry_after_ms: retry_after.map(|d| d.as_millis()),
+            delay_ms: outcome.delay.map(|d| d.as_millis()),
+            reason: outcome.reason,
+            rules: rules.into_iter().map(RuleResponse::from).collect(),
+            quotas: quotas.into_iter().map(RuleResponse::from).collect(),
             metadata: Metadata {
                 x_ratelimit_remaning: remaning_requests,
-                x_ratelimit_limit: _data.config.limit(),
-                id: payload.id,
+                x_ratelimit_limit: limit,
+                id: payload.id.clone(),
+                key: data.config.key().map(|_| key),
+                tier,
                 path: payload.path.clone(),
                 ip: payload.ip.clone(),
+                matched_path,
             },
         })
         .with_header("X-Ratelimit-Remaning", remaning_requests as usize)
-        .with_header("X-Ratelimit-Limit", _data.config.limit() as usize)
+        .with_header("X-Ratelimit-Limit", limit as usize)
+}
+
+// Retry-After is in whole seconds, round up so
+// callers never retry too early.
+fn retry_after_secs(retry_after: Duration) -> usize {
+    (retry_after.as_secs() + (retry_after.subsec_nanos() > 0) as u64) as usize
+}
+
+fn decide(dur: &Dur<Box<dyn Backend>>, id: u64, ip_and_path: IpAndPath, dry_run: bool) -> Decision {
+    if dry_run {
+        dur.check(id, ip_and_path)
+    } else {
+        dur.request(id, ip_and_path)
+    }
+}
+
+#[derive(Serialize)]
+struct IdResponse {
+    id: String,
+    limit: u32,
+    window_time: u64,
+    remaining: u32,
+    // The requests inside the window, and how many of them were
+    // sent from each ip address and to each path. Only the sliding
+    // window log keeps the requests, the other algorithms don't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requests: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_addresses: Option<BTreeMap<IpAddr, usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paths: Option<BTreeMap<String, usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tier: Option<String>,
+    #[serde(rename = "override", skip_serializing_if = "Option::is_none")]
+    limit_override: Option<Override>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    quotas: Vec<QuotaResponse>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    leases: Vec<LeasesResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ban: Option<BanResponse>,
+}
+
+#[derive(Serialize)]
+struct QuotaResponse {
+    name: String,
+    period: String,
+    limit: u64,
+    used: u64,
+    remaining: u64,
+    // When the current period ends, in unix seconds.
+    resets_at: u64,
+}
+
+impl From<QuotaUsage> for QuotaResponse {
+    fn from(quota: QuotaUsage) -> Self {
+        Self {
+            name: quota.name,
+            period: quota.period.to_string(),
+            limit: quota.limit,
+            used: quota.used,
+            remaining: quota.limit.saturating_sub(quota.used),
+            resets_at: quota.resets_at.as_secs(),
+        }
+    }
+}
+
+// The leases of a concurrency limiter the id holds.
+#[derive(Serialize)]
+struct LeasesResponse {
+    name: String,
+    limit: u32,
+    held: usize,
+}
+
+impl From<LeaseUsage> for LeasesResponse {
+    fn from(leases: LeaseUsage) -> Self {
+        Self {
+            name: leases.name,
+            limit: leases.limit,
+            held: leases.held,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BanResponse {
+    // The id the ban is stored under, keys are hashed.
+    id: String,
+    // When the ban ends, in unix milliseconds.
+    until_ms: u64,
+    // How many times in a row the id was banned.
+    offences: u32,
+}
+
+impl BanResponse {
+    fn new(id: u64, ban: Ban) -> Self {
+        Self {
+            id: id.to_string(),
+            until_ms: ban.until,
+            offences: ban.offences,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BansResponse {
+    bans: Vec<BanResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LimitOverride {
+    limit: u32,
+    // The global window time is used otherwise.
+    window_time: Option<u16>,
+    // How long the override lasts, in seconds.
+    ttl: u64,
+}
+
+// Runs an admin call against dur, on the thread pool if the
+// backend blocks. Errors are turned into a response.
+async fn admin<F, T>(data: web::Data<Dur<Box<dyn Backend>>>, f: F) -> Result<T, HttpResponse>
+where
+    F: FnOnce(&Dur<Box<dyn Backend>>) -> Result<T, Box<dyn Error>> + Send + 'static,
+    T: Send + 'static,
+{
+    let result = if data.is_blocking() {
+        web::block(move || f(&data).map_err(|why| why.to_string()))
+            .await
+            .map_err(|why| match why {
+                BlockingError::Error(error) => error,
+                BlockingError::Canceled => "the call was canceled".to_owned(),
+            })
+    } else {
+        f(&data).map_err(|why| why.to_string())
+    };
+
+    result.map_err(|error| HttpResponse::InternalServerError().json(BadRequest { error }))
+}
+
+#[get("/ids/{id}")]
+pub async fn get_id(
+    key: web::Path<String>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> HttpResponse {
+    let key = key.into_inner();
+    let id = data.config.id_of(&key);
+    let tier = data.config.tier_of(&key).map(ToOwned::to_owned);
+
+    let name = tier.clone();
+    let result = admin(data, move |dur| {
+        Ok((
+            dur.usage(id, name.as_deref())?,
+            dur.limit_override(id)?,
+            dur.quota_usage(id)?,
+            dur.lease_usage(id)?,
+            dur.ban(id)?,
+        ))
+    })
+    .await;
+
+    match result {
+        Ok((usage, limit_override, quotas, leases, ban)) => {
+            let requests = usage.requests;
+
+            HttpResponse::Ok().json(IdResponse {
+                id: key,
+                limit: usage.rate.limit,
+                window_time: usage.rate.period.as_secs(),
+                remaining: usage.remaining,
+                requests: requests.as_ref().map(|requests| requests.requests),
+                ip_addresses: requests
+                    .as_ref()
+                    .map(|requests| requests.ip_addresses.clone()),
+                paths: requests.map(|requests| requests.paths),
+                tier,
+                limit_override,
+                quotas: quotas.into_iter().map(QuotaResponse::from).collect(),
+                leases: leases.into_iter().map(LeasesResponse::from).collect(),
+                ban: ban.map(|ban| BanResponse::new(id, ban)),
+            })
+        }
+        Err(response) => response,
+    }
+}
+
+#[delete("/ids/{id}")]
+pub async fn delete_id(
+    key: web::Path<String>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> HttpResponse {
+    let id = data.config.id_of(&key);
+
+    match admin(data, move |dur| dur.reset(id)).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(response) => response,
+    }
+}
+
+#[put("/ids/{id}/limit")]
+pub async fn put_limit(
+    key: web::Path<String>,
+    payload: web::Json<LimitOverride>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> HttpResponse {
+    if payload.ttl == 0 {
+        return HttpResponse::BadRequest().json(BadRequest {
+            error: "ttl must be at least a second".to_owned(),
+        });
+    }
+
+    let id = data.config.id_of(&key);
+    let limit = Override {
+        limit: payload.limit,
+        window_time: payload
+            .window_time
+            .unwrap_or_else(|| data.config.window_time()),
+    };
+    let ttl = Duration::from_secs(payload.ttl);
+
+    match admin(data, move |dur| dur.override_limit(id, limit, ttl)).await {
+        Ok(()) => HttpResponse::Ok().json(LimitOverride {
+            limit: limit.limit,
+            window_time: Some(limit.window_time),
+            ttl: payload.ttl,
+        }),
+        Err(response) => response,
+    }
+}
+
+// Lists the ids that are banned, the ones banned the longest first.
+#[get("/bans")]
+pub async fn get_bans(data: web::Data<Dur<Box<dyn Backend>>>) -> HttpResponse {
+    match admin(data, |dur| dur.bans()).await {
+        Ok(mut bans) => {
+            bans.sort_by_key(|(_, ban)| std::cmp::Reverse(ban.until));
+
+            HttpResponse::Ok().json(BansResponse {
+                bans: bans
+                    .into_iter()
+                    .map(|(id, ban)| BanResponse::new(id, ban))
+                    .collect(),
+            })
+        }
+        Err(response) => response,
+    }
+}
+
+#[delete("/bans/{id}")]
+pub async fn delete_ban(
+    key: web::Path<String>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> HttpResponse {
+    let id = data.config.id_of(&key);
+
+    match admin(data, move |dur| dur.lift_ban(id)).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(BadRequest {
+            error: format!("{} is not banned", key),
+        }),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AcquireRequest {
+    // The name of the concurrency limiter the lease is taken from.
+    limiter: String,
+    // The id or the fields the key is built from,
+    // the same as the ones of a request.
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Serialize)]
+struct AcquireResponse {
+    allowed: bool,
+    // The lease to release once the request is done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    // When the lease expires if it isn't released, in unix milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at_ms: Option<u128>,
+    // How long a banned id has to wait.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<Reason>,
+    limiter: String,
+    limit: u32,
+    remaining: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReleaseRequest {
+    token: String,
+}
+
+// Takes a lease on one of the requests the id can have
+// in flight, which is held until it is released.
+#[post("/acquire")]
+pub async fn acquire_lease(
+    payload: web::Json<AcquireRequest>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> HttpResponse {
+    let request = &payload.request;
+    let (_, id) = match request.key(data.config.key(), &data.config.hasher()) {
+        Ok(key) => key,
+        Err(error) => return HttpResponse::BadRequest().json(BadRequest { error }),
+    };
+    let ip_and_path = match request.ip_addr() {
+        Ok(ip_addr) => IpAndPath::new(ip_addr, request.path.clone()),
+        Err(error) => return HttpResponse::BadRequest().json(BadRequest { error }),
+    };
+    let limiter = payload.limiter.clone();
+
+    let name = limiter.clone();
+    match admin(data, move |dur| dur.acquire_lease(id, &ip_and_path, &name)).await {
+        Ok(Some(LeaseOutcome {
+            outcome,
+            limit,
+            lease,
+        })) => {
+            let retry_after = outcome.retry_after.filter(|_| !outcome.allowed);
+            let mut response = HttpResponse::Ok();
+            if let Some(retry_after) = retry_after {
+                response.header("Retry-After", retry_after_secs(retry_after));
+            }
+
+            response.json(AcquireResponse {
+                allowed: outcome.allowed,
+                expires_at_ms: lease.as_ref().map(|lease| lease.expires_at.as_millis()),
+                token: lease.map(|lease| lease.token),
+                retry_after_ms: retry_after.map(|d| d.as_millis()),
+                reason: outcome.reason,
+                limiter,
+                limit,
+                remaining: outcome.remaining,
+            })
+        }
+        Ok(None) => HttpResponse::BadRequest().json(BadRequest {
+            error: format!("unknown concurrency limiter: {}", limiter),
+        }),
+        Err(response) => response,
+    }
+}
+
+// Gives back a lease before it expires.
+#[post("/release")]
+pub async fn release_lease(
+    payload: web::Json<ReleaseRequest>,
+    data: web::Data<Dur<Box<dyn Backend>>>,
+) -> HttpResponse {
+    let token = payload.into_inner().token;
+
+    match admin(data, move |dur| dur.release_lease(&token)).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(BadRequest {
+            error: "unknown or expired lease".to_owned(),
+        }),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> Request {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_key() {
+        let hasher = KeyHasher::new("secret");
+
+        // numeric ids are stored as they are.
+        let (key, id) = request(r#"{"id": 1234}"#).key(None, &hasher).unwrap();
+        assert_eq!((key.as_str(), id), ("1234", 1234));
+
+        let (key, id) = request(r#"{"id": "api-key"}"#).key(None, &hasher).unwrap();
+        assert_eq!((key.as_str(), id), ("api-key", hasher.id_of("api-key")));
+        assert!(request(r#"{"path": "/orders"}"#)
+            .key(None, &hasher)
+            .is_err());
+
+        let template: KeyTemplate = "{tenant}:{user}:{path}".parse().unwrap();
+        let payload = request(r#"{"tenant": "acme", "user": 42, "path": "/orders"}"#);
+        let (key, id) = payload.key(Some(&template), &hasher).unwrap();
+        assert_eq!(
+            (key.as_str(), id),
+            ("acme:42:/orders", hasher.id_of("acme:42:/orders"))
+        );
+
+        let payload = request(r#"{"tenant": "acme", "path": "/orders"}"#);
+        assert_eq!(
+            payload.key(Some(&template), &hasher),
+            Err("missing field: user".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_acquire_request() {
+        let payload: AcquireRequest =
+            serde_json::from_str(r#"{"limiter": "reports", "tenant": "acme", "id": 42}"#).unwrap();
+        assert_eq!(payload.limiter, "reports");
+
+        let template: KeyTemplate = "{tenant}:{id}".parse().unwrap();
+        let (key, _) = payload
+            .request
+            .key(Some(&template), &KeyHasher::new("secret"))
+            .unwrap();
+        assert_eq!(key, "acme:42");
+    }
 }
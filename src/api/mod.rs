@@ -1,3 +1,6 @@
 mod handlers;
 
-pub use handlers::{get_health, new_request};
+pub use handlers::{
+    acquire_lease, check_request, delete_ban, delete_id, get_bans, get_health, get_id, new_request,
+    put_limit, release_lease,
+};
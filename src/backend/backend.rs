@@ -1,16 +1,507 @@
-use std::{error::Error, net::Ipv4Addr, time::Duration};
+use std::{collections::BTreeMap, error::Error, net::IpAddr, time::Duration};
+
+use serde::{Deserialize, Serialize};
 
 use super::IpAndPath;
+use crate::algorithm::{Outcome, Rate};
 
 // Use the selected backend for storing related information
-// about the API.
-pub trait Backend {
-    fn new() -> Self;
-    fn clear(&mut self);
-    fn evict_older_timestamps(&mut self, id: u64, timestamp: Duration, window_time: u16);
-    fn insert(&mut self, id: u64, ip_and_path: IpAndPath) -> Result<usize, Box<dyn Error>>;
+// about the API. Backends are shared between the workers,
+// so they have to synchronize their own state.
+pub trait Backend: Send + Sync {
+    fn clear(&self);
+
+    // Records the request in the log of the id and evicts the
+    // timestamps older than the windows, in a single step. Returns
+    // the number of requests left in each window. With limits,
+    // the request is only kept if none of the counts goes over
+    // its limit, the counts are returned as if it was kept.
+    fn record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+        limits: Option<Counts>,
+    ) -> Result<Counts, Box<dyn Error>>;
+
+    // Records the ip address the id sent a request from, unless
+    // the id already sent requests from `limit` other addresses
+    // inside the window. Returns whether it was recorded.
+    fn record_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    // The counts `record` would return for the request,
+    // without recording it.
+    fn peek_record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Counts, Box<dyn Error>>;
+
+    // Whether `record_ip_address` would record the
+    // ip address, without recording it.
+    fn peek_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>>;
+
     fn len(&self) -> usize;
     fn request_count(&self, id: u64) -> usize;
-    fn ip_address_count(&self, id: u64, ip: Ipv4Addr) -> usize;
+    fn ip_address_count(&self, id: u64, ip: IpAddr) -> usize;
     fn path_count(&self, id: u64, path: String) -> usize;
+    fn unique_ip_address_count(&self, id: u64) -> usize;
+
+    // Counts a request against the state of the given rate
+    // stored under the key. The limit of an id uses `record`
+    // for the sliding window log instead, but rules with that
+    // algorithm come through here and keep their whole log in
+    // the state, which is read and written on every request.
+    fn acquire(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>>;
+
+    // The outcome `acquire` would return for the
+    // request, without updating the state.
+    fn peek(&self, key: String, timestamp: Duration, rate: Rate)
+        -> Result<Outcome, Box<dyn Error>>;
+
+    // Gives back a request `acquire` allowed at the timestamp, when
+    // it's denied by another limit. A state that expired or was
+    // replaced in the meantime is left alone.
+    fn release(&self, key: String, timestamp: Duration, rate: Rate) -> Result<(), Box<dyn Error>>;
+
+    // Adds the amount to the counter stored under the key, unless
+    // it would go over the limit, in a single step. Returns the
+    // counter with the amount, whether it was added or not. The
+    // counter starts over from zero once it expires.
+    fn add(
+        &self,
+        key: String,
+        amount: u64,
+        limit: u64,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<u64, Box<dyn Error>>;
+
+    fn counter(&self, key: String, timestamp: Duration) -> Result<u64, Box<dyn Error>>;
+
+    // Takes an amount `add` added back from the counter stored
+    // under the key, unless it expired in the meantime.
+    fn subtract(&self, key: String, amount: u64, timestamp: Duration)
+        -> Result<(), Box<dyn Error>>;
+
+    // Takes a lease for the token under the key, unless `limit`
+    // leases that haven't expired are already held there, in a
+    // single step. Expired leases are reclaimed on the way. Returns
+    // the number of leases held with the new one, whether it was
+    // taken or not.
+    fn take_lease(
+        &self,
+        key: String,
+        token: String,
+        limit: usize,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<usize, Box<dyn Error>>;
+
+    // Gives the lease back before it expires. Returns
+    // whether it was still held.
+    fn release_lease(
+        &self,
+        key: String,
+        token: String,
+        timestamp: Duration,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    // The number of leases held under the key
+    // that haven't expired.
+    fn lease_count(&self, key: String, timestamp: Duration) -> Result<usize, Box<dyn Error>>;
+
+    // The requests in the log of the id inside the windows, and
+    // how many of them were sent from each ip address and to
+    // each path.
+    fn usage(
+        &self,
+        id: u64,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Usage, Box<dyn Error>>;
+
+    // Drops the log, the ip addresses, the states, the counters,
+    // the leases and the ban of the id, as if it never sent a
+    // request.
+    fn reset(&self, id: u64) -> Result<(), Box<dyn Error>>;
+
+    // Replaces the limit of the id with the given one,
+    // until the ttl runs out.
+    fn set_override(
+        &self,
+        id: u64,
+        limit: Override,
+        timestamp: Duration,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>>;
+
+    fn get_override(
+        &self,
+        id: u64,
+        timestamp: Duration,
+    ) -> Result<Option<Override>, Box<dyn Error>>;
+
+    // Bans the id until the ban ends. The ban is remembered
+    // until it expires, so the next one can be longer.
+    fn set_ban(
+        &self,
+        id: u64,
+        ban: Ban,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<(), Box<dyn Error>>;
+
+    // The last ban of the id that didn't expire yet,
+    // whether it already ended or not.
+    fn get_ban(&self, id: u64, timestamp: Duration) -> Result<Option<Ban>, Box<dyn Error>>;
+
+    // The ids that are banned, along with their bans.
+    fn bans(&self, timestamp: Duration) -> Result<Vec<(u64, Ban)>, Box<dyn Error>>;
+
+    // Forgets the ban of the id. Returns whether
+    // the id was banned.
+    fn lift_ban(&self, id: u64, timestamp: Duration) -> Result<bool, Box<dyn Error>>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Drops the ids with no requests left in the window and
+    // the expired states, returns how many were dropped. Backends
+    // that expire their keys on their own don't need to.
+    fn sweep(&self, _timestamp: Duration, _window_time: u16) -> usize {
+        0
+    }
+
+    // Whether the calls block on network I/O, in which
+    // case they are run off the async workers.
+    fn is_blocking(&self) -> bool {
+        false
+    }
+}
+
+impl<B> Backend for Box<B>
+where
+    B: Backend + ?Sized,
+{
+    fn clear(&self) {
+        (**self).clear()
+    }
+
+    fn record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+        limits: Option<Counts>,
+    ) -> Result<Counts, Box<dyn Error>> {
+        (**self).record(id, ip_and_path, timestamp, windows, limits)
+    }
+
+    fn record_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        (**self).record_ip_address(id, ip, timestamp, window_time, limit)
+    }
+
+    fn peek_record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Counts, Box<dyn Error>> {
+        (**self).peek_record(id, ip_and_path, timestamp, windows)
+    }
+
+    fn peek_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        (**self).peek_ip_address(id, ip, timestamp, window_time, limit)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn request_count(&self, id: u64) -> usize {
+        (**self).request_count(id)
+    }
+
+    fn ip_address_count(&self, id: u64, ip: IpAddr) -> usize {
+        (**self).ip_address_count(id, ip)
+    }
+
+    fn path_count(&self, id: u64, path: String) -> usize {
+        (**self).path_count(id, path)
+    }
+
+    fn unique_ip_address_count(&self, id: u64) -> usize {
+        (**self).unique_ip_address_count(id)
+    }
+
+    fn acquire(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        (**self).acquire(key, timestamp, rate)
+    }
+
+    fn peek(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        (**self).peek(key, timestamp, rate)
+    }
+
+    fn release(&self, key: String, timestamp: Duration, rate: Rate) -> Result<(), Box<dyn Error>> {
+        (**self).release(key, timestamp, rate)
+    }
+
+    fn add(
+        &self,
+        key: String,
+        amount: u64,
+        limit: u64,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<u64, Box<dyn Error>> {
+        (**self).add(key, amount, limit, timestamp, expires_at)
+    }
+
+    fn counter(&self, key: String, timestamp: Duration) -> Result<u64, Box<dyn Error>> {
+        (**self).counter(key, timestamp)
+    }
+
+    fn subtract(
+        &self,
+        key: String,
+        amount: u64,
+        timestamp: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        (**self).subtract(key, amount, timestamp)
+    }
+
+    fn take_lease(
+        &self,
+        key: String,
+        token: String,
+        limit: usize,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<usize, Box<dyn Error>> {
+        (**self).take_lease(key, token, limit, timestamp, expires_at)
+    }
+
+    fn release_lease(
+        &self,
+        key: String,
+        token: String,
+        timestamp: Duration,
+    ) -> Result<bool, Box<dyn Error>> {
+        (**self).release_lease(key, token, timestamp)
+    }
+
+    fn lease_count(&self, key: String, timestamp: Duration) -> Result<usize, Box<dyn Error>> {
+        (**self).lease_count(key, timestamp)
+    }
+
+    fn usage(
+        &self,
+        id: u64,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Usage, Box<dyn Error>> {
+        (**self).usage(id, timestamp, windows)
+    }
+
+    fn reset(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        (**self).reset(id)
+    }
+
+    fn set_override(
+        &self,
+        id: u64,
+        limit: Override,
+        timestamp: Duration,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        (**self).set_override(id, limit, timestamp, ttl)
+    }
+
+    fn get_override(
+        &self,
+        id: u64,
+        timestamp: Duration,
+    ) -> Result<Option<Override>, Box<dyn Error>> {
+        (**self).get_override(id, timestamp)
+    }
+
+    fn set_ban(
+        &self,
+        id: u64,
+        ban: Ban,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        (**self).set_ban(id, ban, timestamp, expires_at)
+    }
+
+    fn get_ban(&self, id: u64, timestamp: Duration) -> Result<Option<Ban>, Box<dyn Error>> {
+        (**self).get_ban(id, timestamp)
+    }
+
+    fn bans(&self, timestamp: Duration) -> Result<Vec<(u64, Ban)>, Box<dyn Error>> {
+        (**self).bans(timestamp)
+    }
+
+    fn lift_ban(&self, id: u64, timestamp: Duration) -> Result<bool, Box<dyn Error>> {
+        (**self).lift_ban(id, timestamp)
+    }
+
+    fn sweep(&self, timestamp: Duration, window_time: u16) -> usize {
+        (**self).sweep(timestamp, window_time)
+    }
+
+    fn is_blocking(&self) -> bool {
+        (**self).is_blocking()
+    }
+}
+
+// The window times of the requests of an id, and of the
+// requests it sends from an ip address or to a path, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Windows {
+    pub id: u16,
+    pub ip: u16,
+    pub path: u16,
+}
+
+impl Windows {
+    // The same window time for every dimension.
+    pub fn new(window_time: u16) -> Self {
+        Self {
+            id: window_time,
+            ip: window_time,
+            path: window_time,
+        }
+    }
+
+    pub fn longest(&self) -> u16 {
+        self.id.max(self.ip).max(self.path)
+    }
+}
+
+// The number of requests of an id, and the number of them
+// sent from the ip address and to the path of the recorded
+// request, each inside its own window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub requests: usize,
+    pub ip: usize,
+    pub path: usize,
+}
+
+impl Counts {
+    // Whether any of the counts is over the same count
+    // of the limits.
+    pub fn exceeds(&self, limits: &Counts) -> bool {
+        self.requests > limits.requests || self.ip > limits.ip || self.path > limits.path
+    }
+}
+
+// The requests of an id inside its windows, by the ip
+// addresses they were sent from and the paths they were sent to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub requests: usize,
+    pub ip_addresses: BTreeMap<IpAddr, usize>,
+    pub paths: BTreeMap<String, usize>,
+}
+
+// A limit that temporarily replaces the limit of an id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Override {
+    pub limit: u32,
+    pub window_time: u16,
+}
+
+// A ban of an id that was denied too many times, and how many
+// times it was banned before, which makes the next ban longer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ban {
+    // When the ban ends, in unix milliseconds.
+    pub until: u64,
+    pub offences: u32,
+}
+
+impl Ban {
+    pub fn is_active(&self, timestamp: Duration) -> bool {
+        self.until > timestamp.as_millis() as u64
+    }
+}
+
+// The backends that can be selected from the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Memory,
+    ShardedMemory,
+    Redis,
+}
+
+impl BackendKind {
+    pub const VARIANTS: &'static [&'static str] = &["memory", "sharded_memory", "redis"];
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(BackendKind::Memory),
+            "sharded_memory" => Ok(BackendKind::ShardedMemory),
+            "redis" => Ok(BackendKind::Redis),
+            _ => Err(format!("unknown backend: {}", s)),
+        }
+    }
 }
@@ -1,31 +1,139 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     error::Error,
-    net::Ipv4Addr,
+    hash::Hash,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, RwLock,
+    },
     time::{Duration, SystemTime},
 };
 
-use crate::Backend;
-// In memory baceknd for dur
-#[derive(Debug, Clone)]
+use crate::{
+    algorithm::{Outcome, Rate, State},
+    Backend, Ban, Counts, Override, Usage, Windows,
+};
+
+// The requests of an id, oldest first. Requests are appended
+// in arrival order, so requests arriving in the same instant
+// are all kept and the expired ones are always at the front.
+type Log = VecDeque<(Duration, IpAndPath)>;
+
+// The ip addresses an id sent requests from,
+// and the last time it did from each of them.
+type IpAddresses = HashMap<IpAddr, Duration>;
+
+// The tokens of the leases held under a key,
+// and when each of them expires.
+type Leases = HashMap<String, Duration>;
+
+// In memory baceknd for dur. Every id is locked on its own,
+// so requests for different ids don't wait for each other.
+#[derive(Debug)]
 pub struct Memory {
-    record: HashMap<u64, HashMap<Duration, IpAndPath>>,
+    record: RwLock<HashMap<u64, Entry<Log>>>,
+    ip_addresses: RwLock<HashMap<u64, Entry<IpAddresses>>>,
+    states: RwLock<HashMap<String, Entry<Expiring>>>,
+    counters: RwLock<HashMap<String, Entry<Counter>>>,
+    leases: RwLock<HashMap<String, Entry<Leases>>>,
+    // The overridden limits, and when they expire.
+    overrides: RwLock<HashMap<u64, (Override, Duration)>>,
+    // The bans of the ids, and when they are forgotten.
+    bans: RwLock<HashMap<u64, (Ban, Duration)>>,
+    // The maximum number of ids, and of states, that are kept.
+    // The least recently used ones are evicted to make room.
+    // Counters and leases are never evicted, they hold what an
+    // id used until they expire and are swept.
+    max_keys: Option<usize>,
 }
+
+// A value in one of the maps, along with when it was last
+// used in milliseconds, for the least recently used eviction.
+#[derive(Debug)]
+struct Entry<V> {
+    value: Mutex<V>,
+    last_used: AtomicU64,
+}
+
+impl<V> Entry<V> {
+    fn new(value: V, timestamp: Duration) -> Self {
+        Self {
+            value: Mutex::new(value),
+            last_used: AtomicU64::new(timestamp.as_millis() as u64),
+        }
+    }
+
+    fn touch(&self, timestamp: Duration) {
+        self.last_used
+            .fetch_max(timestamp.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+// The state of a key and the time it stops holding any
+// information, after which it can be dropped.
+#[derive(Debug)]
+struct Expiring {
+    state: State,
+    expires_at: Duration,
+}
+
+// A counter that starts over once it expires.
+#[derive(Debug)]
+struct Counter {
+    count: u64,
+    expires_at: Duration,
+}
+
 #[derive(Debug, Clone)]
 pub struct IpAndPath {
-    pub ip: Option<Ipv4Addr>,
+    pub ip: Option<IpAddr>,
     pub path: Option<String>,
+    pub method: Option<String>,
+    // The name of the tier of the id.
+    pub tier: Option<String>,
+    // How many requests the request counts as, the
+    // cost of the rules it matches or one by default.
+    pub cost: Option<u32>,
 }
 
 impl IpAndPath {
-    pub fn new(ip: Option<Ipv4Addr>, path: Option<String>) -> Self {
-        Self { ip: ip, path: path }
+    pub fn new(ip: Option<IpAddr>, path: Option<String>) -> Self {
+        Self {
+            ip,
+            path,
+            method: None,
+            tier: None,
+            cost: None,
+        }
+    }
+
+    pub fn with_method(mut self, method: Option<String>) -> Self {
+        self.method = method;
+        self
     }
 
-    pub fn from_ip_addr(ip: Ipv4Addr) -> Self {
+    pub fn with_tier(mut self, tier: Option<String>) -> Self {
+        self.tier = tier;
+        self
+    }
+
+    pub fn with_cost(mut self, cost: Option<u32>) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    pub fn cost(&self) -> usize {
+        self.cost.unwrap_or(1).max(1) as usize
+    }
+
+    pub fn from_ip_addr<T: Into<IpAddr>>(ip: T) -> Self {
         Self {
-            ip: Some(ip),
+            ip: Some(ip.into()),
             path: None,
+            method: None,
+            tier: None,
+            cost: None,
         }
     }
 
@@ -33,81 +141,717 @@ impl IpAndPath {
         Self {
             ip: None,
             path: Some(path),
+            method: None,
+            tier: None,
+            cost: None,
         }
     }
 }
 
-impl Backend for Memory {
-    fn new() -> Self {
+impl Memory {
+    pub fn new() -> Self {
         Self {
-            record: HashMap::new(),
+            record: RwLock::new(HashMap::new()),
+            ip_addresses: RwLock::new(HashMap::new()),
+            states: RwLock::new(HashMap::new()),
+            counters: RwLock::new(HashMap::new()),
+            leases: RwLock::new(HashMap::new()),
+            overrides: RwLock::new(HashMap::new()),
+            bans: RwLock::new(HashMap::new()),
+            max_keys: None,
         }
     }
 
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys.max(1));
+        self
+    }
+
     // inserts the incoming request to the
-    fn insert(&mut self, id: u64, ip_and_path: IpAndPath) -> Result<usize, Box<dyn Error>> {
-        let key = self.record.entry(id).or_insert(HashMap::new());
-        key.insert(
-            SystemTime::now().duration_since(std::time::UNIX_EPOCH)?,
-            ip_and_path,
-        );
+    pub fn insert(&self, id: u64, ip_and_path: IpAndPath) -> Result<usize, Box<dyn Error>> {
+        let timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        Ok(
+            self.with_entry(&self.record, id, timestamp, Log::new, |logs| {
+                push(logs, timestamp, ip_and_path);
+                logs.iter().map(|(_, ip_and_path)| ip_and_path.cost()).sum()
+            }),
+        )
+    }
+
+    pub fn evict_older_timestamps(&self, id: u64, timestamp: Duration, window_time: u16) {
+        if let Some(logs) = self.record.read().unwrap().get(&id) {
+            evict(&mut logs.value.lock().unwrap(), timestamp, window_time);
+        }
+    }
+
+    fn count<F>(&self, id: u64, filter: F) -> usize
+    where
+        F: Fn(&IpAndPath) -> bool,
+    {
+        match self.record.read().unwrap().get(&id) {
+            Some(v) => v
+                .value
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, ip_and_path)| filter(ip_and_path))
+                .map(|(_, ip_and_path)| ip_and_path.cost())
+                .sum(),
+            None => 0,
+        }
+    }
+
+    // Runs `f` with the value stored under the key, creating it
+    // if it doesn't exist. The least recently used keys are
+    // evicted when the map is full.
+    fn with_entry<K, V, D, F, R>(
+        &self,
+        map: &RwLock<HashMap<K, Entry<V>>>,
+        key: K,
+        timestamp: Duration,
+        default: D,
+        f: F,
+    ) -> R
+    where
+        K: Eq + Hash + Clone,
+        D: FnOnce() -> V,
+        F: FnOnce(&mut V) -> R,
+    {
+        with_entry_in(map, key, timestamp, self.max_keys, default, f)
+    }
+
+    // The same, without ever evicting a key to make room.
+    fn with_kept_entry<K, V, D, F, R>(
+        &self,
+        map: &RwLock<HashMap<K, Entry<V>>>,
+        key: K,
+        timestamp: Duration,
+        default: D,
+        f: F,
+    ) -> R
+    where
+        K: Eq + Hash + Clone,
+        D: FnOnce() -> V,
+        F: FnOnce(&mut V) -> R,
+    {
+        with_entry_in(map, key, timestamp, None, default, f)
+    }
+}
+
+// The map is only locked for writing when a key is seen for the
+// first time, which is also when the least recently used keys
+// are evicted if there are `max_keys` of them already.
+fn with_entry_in<K, V, D, F, R>(
+    map: &RwLock<HashMap<K, Entry<V>>>,
+    key: K,
+    timestamp: Duration,
+    max_keys: Option<usize>,
+    default: D,
+    f: F,
+) -> R
+where
+    K: Eq + Hash + Clone,
+    D: FnOnce() -> V,
+    F: FnOnce(&mut V) -> R,
+{
+    if let Some(entry) = map.read().unwrap().get(&key) {
+        entry.touch(timestamp);
+        return f(&mut entry.value.lock().unwrap());
+    }
+
+    let mut map = map.write().unwrap();
+    if let Some(max_keys) = max_keys {
+        if map.len() >= max_keys && !map.contains_key(&key) {
+            // evict a tenth at once, so a flood of new keys
+            // doesn't scan the whole map for every one of them.
+            let count = map.len() + 1 - max_keys + max_keys / 10;
+            evict_least_recently_used(&mut map, count);
+        }
+    }
+
+    let entry = map
+        .entry(key)
+        .or_insert_with(|| Entry::new(default(), timestamp));
+    entry.touch(timestamp);
+
+    f(entry.value.get_mut().unwrap())
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for Memory {
+    fn record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+        limits: Option<Counts>,
+    ) -> Result<Counts, Box<dyn Error>> {
+        Ok(
+            self.with_entry(&self.record, id, timestamp, Log::new, |logs| {
+                push(logs, timestamp, ip_and_path);
+                evict(logs, timestamp, windows.longest());
+                let counts = counts(logs, timestamp, windows);
+                if limits.is_some_and(|limits| counts.exceeds(&limits)) {
+                    logs.pop_back();
+                }
 
-        Ok(key.len())
+                counts
+            }),
+        )
     }
 
-    fn clear(&mut self) {
-        self.record.clear();
+    fn record_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        let ip_addresses = &self.ip_addresses;
+
+        Ok(self.with_entry(
+            ip_addresses,
+            id,
+            timestamp,
+            IpAddresses::new,
+            |ip_addresses| {
+                ip_addresses.retain(|_, seen| within(*seen, timestamp, window_time));
+                if !ip_addresses.contains_key(&ip) && ip_addresses.len() >= limit {
+                    return false;
+                }
+
+                ip_addresses.insert(ip, timestamp);
+                true
+            },
+        ))
+    }
+
+    // The request is recorded in a copy of the log.
+    fn peek_record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Counts, Box<dyn Error>> {
+        let mut logs = match self.record.read().unwrap().get(&id) {
+            Some(logs) => logs.value.lock().unwrap().clone(),
+            None => Log::new(),
+        };
+        push(&mut logs, timestamp, ip_and_path);
+
+        Ok(counts(&logs, timestamp, windows))
+    }
+
+    fn peek_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        let ip_addresses = self.ip_addresses.read().unwrap();
+        let ip_addresses = match ip_addresses.get(&id) {
+            Some(ip_addresses) => ip_addresses.value.lock().unwrap(),
+            None => return Ok(limit > 0),
+        };
+
+        let recent = |seen: &Duration| within(*seen, timestamp, window_time);
+        if ip_addresses.get(&ip).is_some_and(recent) {
+            return Ok(true);
+        }
+
+        Ok(ip_addresses.values().filter(|seen| recent(seen)).count() < limit)
+    }
+
+    fn clear(&self) {
+        self.record.write().unwrap().clear();
+        self.ip_addresses.write().unwrap().clear();
+        self.states.write().unwrap().clear();
+        self.counters.write().unwrap().clear();
+        self.leases.write().unwrap().clear();
+        self.overrides.write().unwrap().clear();
+        self.bans.write().unwrap().clear();
     }
 
     fn len(&self) -> usize {
-        self.record.len()
+        self.record.read().unwrap().len()
+            + self.ip_addresses.read().unwrap().len()
+            + self.states.read().unwrap().len()
+            + self.counters.read().unwrap().len()
+            + self.leases.read().unwrap().len()
+            + self.overrides.read().unwrap().len()
+            + self.bans.read().unwrap().len()
     }
 
     // Get the current request count of the id
     fn request_count(&self, id: u64) -> usize {
-        match self.record.get(&id) {
+        self.count(id, |_| true)
+    }
+
+    fn ip_address_count(&self, id: u64, ip: IpAddr) -> usize {
+        self.count(id, |ip_and_path| ip_and_path.ip == Some(ip))
+    }
+
+    fn path_count(&self, id: u64, path: String) -> usize {
+        self.count(id, |ip_and_path| ip_and_path.path.as_ref() == Some(&path))
+    }
+
+    fn unique_ip_address_count(&self, id: u64) -> usize {
+        match self.ip_addresses.read().unwrap().get(&id) {
+            Some(ip_addresses) => ip_addresses.value.lock().unwrap().len(),
             None => 0,
-            Some(v) => v.len(),
         }
     }
 
-    fn evict_older_timestamps(&mut self, id: u64, timestamp: Duration, window_time: u16) {
-        match self.record.get_mut(&id) {
-            Some(logs) => {
-                for (duration, _) in logs.clone().iter() {
-                    if (timestamp.as_secs() - duration.as_secs()) > window_time as u64 {
-                        logs.remove(duration);
-                    }
+    fn acquire(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let new_state = || Expiring {
+            state: State::new(timestamp, &rate),
+            expires_at: timestamp,
+        };
+
+        Ok(
+            self.with_entry(&self.states, key, timestamp, new_state, |expiring| {
+                if expiring.state.algorithm() != rate.algorithm {
+                    expiring.state = State::new(timestamp, &rate);
                 }
+
+                expiring.expires_at = timestamp.saturating_add(rate.expiry());
+                expiring.state.acquire(timestamp, &rate)
+            }),
+        )
+    }
+
+    // The request is counted against a copy of the state.
+    fn peek(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let mut state = self
+            .states
+            .read()
+            .unwrap()
+            .get(&key)
+            .map(|expiring| expiring.value.lock().unwrap().state.clone())
+            .filter(|state| state.algorithm() == rate.algorithm)
+            .unwrap_or_else(|| State::new(timestamp, &rate));
+
+        Ok(state.acquire(timestamp, &rate))
+    }
+
+    fn release(&self, key: String, timestamp: Duration, rate: Rate) -> Result<(), Box<dyn Error>> {
+        if let Some(expiring) = self.states.read().unwrap().get(&key) {
+            let mut expiring = expiring.value.lock().unwrap();
+            if expiring.state.algorithm() == rate.algorithm && expiring.expires_at > timestamp {
+                expiring.state.release(timestamp, &rate);
             }
-            None => return,
         }
+
+        Ok(())
     }
 
-    fn ip_address_count(&self, id: u64, ip: Ipv4Addr) -> usize {
-        match self.record.get(&id) {
-            Some(v) => v
-                .iter()
-                .filter(|(_, ip_and_path)| match ip_and_path.ip.clone() {
-                    Some(addr) => addr == ip,
-                    None => false,
-                })
-                .count(),
+    fn add(
+        &self,
+        key: String,
+        amount: u64,
+        limit: u64,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<u64, Box<dyn Error>> {
+        let new_counter = || Counter {
+            count: 0,
+            expires_at,
+        };
+
+        Ok(
+            self.with_kept_entry(&self.counters, key, timestamp, new_counter, |counter| {
+                if counter.expires_at <= timestamp {
+                    *counter = new_counter();
+                }
+
+                let count = counter.count.saturating_add(amount);
+                if count <= limit {
+                    counter.count = count;
+                    counter.expires_at = expires_at;
+                }
+
+                count
+            }),
+        )
+    }
+
+    fn counter(&self, key: String, timestamp: Duration) -> Result<u64, Box<dyn Error>> {
+        Ok(match self.counters.read().unwrap().get(&key) {
+            Some(counter) => {
+                let counter = counter.value.lock().unwrap();
+                if counter.expires_at > timestamp {
+                    counter.count
+                } else {
+                    0
+                }
+            }
             None => 0,
+        })
+    }
+
+    fn subtract(
+        &self,
+        key: String,
+        amount: u64,
+        timestamp: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(counter) = self.counters.read().unwrap().get(&key) {
+            let mut counter = counter.value.lock().unwrap();
+            if counter.expires_at > timestamp {
+                counter.count = counter.count.saturating_sub(amount);
+            }
         }
+
+        Ok(())
     }
 
-    fn path_count(&self, id: u64, path: String) -> usize {
-        match self.record.get(&id) {
-            Some(v) => v
-                .iter()
-                .filter(|(_, ip_and_path)| match ip_and_path.path.clone() {
-                    Some(_path) => _path == path,
-                    None => false,
-                })
+    fn take_lease(
+        &self,
+        key: String,
+        token: String,
+        limit: usize,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<usize, Box<dyn Error>> {
+        Ok(
+            self.with_kept_entry(&self.leases, key, timestamp, Leases::new, |leases| {
+                leases.retain(|_, expires_at| *expires_at > timestamp);
+
+                let count = leases.len() + 1;
+                if count <= limit {
+                    leases.insert(token, expires_at);
+                }
+
+                count
+            }),
+        )
+    }
+
+    fn release_lease(
+        &self,
+        key: String,
+        token: String,
+        timestamp: Duration,
+    ) -> Result<bool, Box<dyn Error>> {
+        Ok(match self.leases.read().unwrap().get(&key) {
+            Some(leases) => matches!(
+                leases.value.lock().unwrap().remove(&token),
+                Some(expires_at) if expires_at > timestamp
+            ),
+            None => false,
+        })
+    }
+
+    fn lease_count(&self, key: String, timestamp: Duration) -> Result<usize, Box<dyn Error>> {
+        Ok(match self.leases.read().unwrap().get(&key) {
+            Some(leases) => leases
+                .value
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|expires_at| **expires_at > timestamp)
                 .count(),
             None => 0,
+        })
+    }
+
+    fn usage(
+        &self,
+        id: u64,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Usage, Box<dyn Error>> {
+        let mut usage = Usage::default();
+
+        if let Some(logs) = self.record.read().unwrap().get(&id) {
+            for (requested, ip_and_path) in logs.value.lock().unwrap().iter() {
+                let cost = ip_and_path.cost();
+                if within(*requested, timestamp, windows.id) {
+                    usage.requests += cost;
+                }
+                if let (Some(ip), true) =
+                    (ip_and_path.ip, within(*requested, timestamp, windows.ip))
+                {
+                    *usage.ip_addresses.entry(ip).or_default() += cost;
+                }
+                if let (Some(path), true) = (
+                    &ip_and_path.path,
+                    within(*requested, timestamp, windows.path),
+                ) {
+                    *usage.paths.entry(path.clone()).or_default() += cost;
+                }
+            }
         }
+
+        Ok(usage)
+    }
+
+    fn reset(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.record.write().unwrap().remove(&id);
+        self.ip_addresses.write().unwrap().remove(&id);
+        self.bans.write().unwrap().remove(&id);
+
+        // the states of the id are stored under the id, and
+        // the states of its paths, ip addresses and rules, and
+        // its quotas and leases, under keys that start with it.
+        let id = id.to_string();
+        let prefix = format!("{}:", id);
+        self.states
+            .write()
+            .unwrap()
+            .retain(|key, _| *key != id && !key.starts_with(&prefix));
+        self.counters
+            .write()
+            .unwrap()
+            .retain(|key, _| !key.starts_with(&prefix));
+        self.leases
+            .write()
+            .unwrap()
+            .retain(|key, _| !key.starts_with(&prefix));
+
+        Ok(())
+    }
+
+    fn set_override(
+        &self,
+        id: u64,
+        limit: Override,
+        timestamp: Duration,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.overrides
+            .write()
+            .unwrap()
+            .insert(id, (limit, timestamp + ttl));
+
+        Ok(())
+    }
+
+    fn get_override(
+        &self,
+        id: u64,
+        timestamp: Duration,
+    ) -> Result<Option<Override>, Box<dyn Error>> {
+        Ok(match self.overrides.read().unwrap().get(&id) {
+            Some((limit, expires_at)) if *expires_at > timestamp => Some(*limit),
+            _ => None,
+        })
+    }
+
+    fn set_ban(
+        &self,
+        id: u64,
+        ban: Ban,
+        _timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.bans.write().unwrap().insert(id, (ban, expires_at));
+
+        Ok(())
+    }
+
+    fn get_ban(&self, id: u64, timestamp: Duration) -> Result<Option<Ban>, Box<dyn Error>> {
+        Ok(match self.bans.read().unwrap().get(&id) {
+            Some((ban, expires_at)) if *expires_at > timestamp => Some(*ban),
+            _ => None,
+        })
+    }
+
+    fn bans(&self, timestamp: Duration) -> Result<Vec<(u64, Ban)>, Box<dyn Error>> {
+        Ok(self
+            .bans
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, (ban, _))| ban.is_active(timestamp))
+            .map(|(id, (ban, _))| (*id, *ban))
+            .collect())
+    }
+
+    fn lift_ban(&self, id: u64, timestamp: Duration) -> Result<bool, Box<dyn Error>> {
+        Ok(match self.bans.write().unwrap().remove(&id) {
+            Some((ban, _)) => ban.is_active(timestamp),
+            None => false,
+        })
+    }
+
+    // The ids with an override keep their requests for
+    // the window of the override, if it's the longer one.
+    fn sweep(&self, timestamp: Duration, window_time: u16) -> usize {
+        let mut swept = 0;
+
+        let overrides: HashMap<u64, u16> = self
+            .overrides
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, (_, expires_at))| *expires_at > timestamp)
+            .map(|(id, (limit, _))| (*id, limit.window_time))
+            .collect();
+
+        let mut record = self.record.write().unwrap();
+        let len = record.len();
+        record.retain(|id, logs| {
+            let logs = logs.value.get_mut().unwrap();
+            let window_time = overrides
+                .get(id)
+                .map_or(window_time, |&overridden| overridden.max(window_time));
+            evict(logs, timestamp, window_time);
+            !logs.is_empty()
+        });
+        swept += len - record.len();
+        drop(record);
+
+        let mut ip_addresses = self.ip_addresses.write().unwrap();
+        let len = ip_addresses.len();
+        ip_addresses.retain(|_, ip_addresses| {
+            let ip_addresses = ip_addresses.value.get_mut().unwrap();
+            ip_addresses.retain(|_, seen| within(*seen, timestamp, window_time));
+            !ip_addresses.is_empty()
+        });
+        swept += len - ip_addresses.len();
+        drop(ip_addresses);
+
+        let mut states = self.states.write().unwrap();
+        let len = states.len();
+        states.retain(|_, expiring| expiring.value.get_mut().unwrap().expires_at > timestamp);
+        swept += len - states.len();
+        drop(states);
+
+        let mut counters = self.counters.write().unwrap();
+        let len = counters.len();
+        counters.retain(|_, counter| counter.value.get_mut().unwrap().expires_at > timestamp);
+        swept += len - counters.len();
+        drop(counters);
+
+        let mut leases = self.leases.write().unwrap();
+        let len = leases.len();
+        leases.retain(|_, leases| {
+            let leases = leases.value.get_mut().unwrap();
+            leases.retain(|_, expires_at| *expires_at > timestamp);
+            !leases.is_empty()
+        });
+        swept += len - leases.len();
+        drop(leases);
+
+        let mut overrides = self.overrides.write().unwrap();
+        let len = overrides.len();
+        overrides.retain(|_, (_, expires_at)| *expires_at > timestamp);
+        swept += len - overrides.len();
+        drop(overrides);
+
+        let mut bans = self.bans.write().unwrap();
+        let len = bans.len();
+        bans.retain(|_, (_, expires_at)| *expires_at > timestamp);
+        swept += len - bans.len();
+
+        swept
+    }
+}
+
+// Appends the request to the log, as a single entry whatever
+// it costs. The timestamps in the log never go backwards, even
+// if the clock does.
+fn push(logs: &mut Log, timestamp: Duration, ip_and_path: IpAndPath) {
+    let timestamp = match logs.back() {
+        Some((last, _)) => timestamp.max(*last),
+        None => timestamp,
+    };
+
+    logs.push_back((timestamp, ip_and_path));
+}
+
+fn evict(logs: &mut Log, timestamp: Duration, window_time: u16) {
+    while let Some((oldest, _)) = logs.front() {
+        if within(*oldest, timestamp, window_time) {
+            break;
+        }
+
+        logs.pop_front();
+    }
+}
+
+// Adds up the costs of the requests inside their windows. The
+// log is kept for the longest window, the ip address and the
+// path are counted for the latest request only.
+fn counts(logs: &Log, timestamp: Duration, windows: Windows) -> Counts {
+    let latest = match logs.back() {
+        Some((_, latest)) => latest,
+        None => return Counts::default(),
+    };
+    let count = |window_time: u16, filter: &dyn Fn(&IpAndPath) -> bool| {
+        logs.iter()
+            .rev()
+            .take_while(|(requested, _)| within(*requested, timestamp, window_time))
+            .filter(|(_, ip_and_path)| filter(ip_and_path))
+            .map(|(_, ip_and_path)| ip_and_path.cost())
+            .sum()
+    };
+
+    Counts {
+        requests: count(windows.id, &|_| true),
+        ip: match latest.ip {
+            Some(ip) => count(windows.ip, &|ip_and_path| ip_and_path.ip == Some(ip)),
+            None => 0,
+        },
+        path: match latest.path {
+            Some(ref path) => count(windows.path, &|ip_and_path| {
+                ip_and_path.path.as_ref() == Some(path)
+            }),
+            None => 0,
+        },
+    }
+}
+
+fn within(requested: Duration, timestamp: Duration, window_time: u16) -> bool {
+    timestamp.as_secs().saturating_sub(requested.as_secs()) <= window_time as u64
+}
+
+// Removes the `count` least recently used keys from the map.
+fn evict_least_recently_used<K, V>(map: &mut HashMap<K, Entry<V>>, count: usize)
+where
+    K: Eq + Hash + Clone,
+{
+    let mut used: Vec<(u64, &K)> = map
+        .iter()
+        .map(|(key, entry)| (entry.last_used.load(Ordering::Relaxed), key))
+        .collect();
+
+    let count = count.min(used.len());
+    if count == 0 {
+        return;
+    }
+    if count < used.len() {
+        used.select_nth_unstable_by_key(count - 1, |(last_used, _)| *last_used);
+    }
+
+    let keys: Vec<K> = used[..count]
+        .iter()
+        .map(|(_, key)| (*key).clone())
+        .collect();
+    for key in keys {
+        map.remove(&key);
     }
 }
 
@@ -116,16 +860,17 @@ mod tests {
     use std::net::Ipv4Addr;
 
     use super::*;
+    use crate::algorithm::Algorithm;
 
     #[test]
     fn test_insert() {
-        let mut mem = Memory::new();
+        let mem = Memory::new();
 
         assert!(mem.insert(1234859, IpAndPath::new(None, None)).is_ok());
     }
     #[test]
     fn test_insert_multiple() {
-        let mut mem = Memory::new();
+        let mem = Memory::new();
 
         assert!(mem.insert(12348591, IpAndPath::new(None, None)).is_ok());
         assert!(mem.insert(12348591, IpAndPath::new(None, None)).is_ok());
@@ -136,7 +881,7 @@ mod tests {
 
     #[test]
     fn test_len_and_cleanup() {
-        let mut mem = Memory::new();
+        let mem = Memory::new();
 
         assert_eq!(mem.len(), 0);
 
@@ -156,7 +901,7 @@ mod tests {
 
     #[test]
     fn test_request_count() {
-        let mut mem = Memory::new();
+        let mem = Memory::new();
 
         assert_eq!(mem.len(), 0);
 
@@ -182,41 +927,683 @@ mod tests {
 
     #[test]
     fn test_unique_ip_addresses() {
-        let mut mem = Memory::new();
+        let mem = Memory::new();
 
         assert_eq!(mem.len(), 0);
 
-        assert!(mem
-            .insert(
+        let now = Duration::from_secs(1000);
+        for i in 1..=5 {
+            let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, i));
+            assert!(mem.record_ip_address(12348591, ip, now, 300, 3).unwrap() == (i <= 3));
+        }
+        assert_eq!(mem.unique_ip_address_count(12348591), 3);
+
+        // peeking tells the same without recording anything.
+        let new_ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 9));
+        let seen_ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 2));
+        assert!(!mem.peek_ip_address(12348591, new_ip, now, 300, 3).unwrap());
+        assert!(mem.peek_ip_address(12348591, seen_ip, now, 300, 3).unwrap());
+        assert!(mem.peek_ip_address(12348591, new_ip, now, 300, 4).unwrap());
+        assert_eq!(mem.unique_ip_address_count(12348591), 3);
+
+        // addresses that were already seen are still recorded.
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        assert!(mem.record_ip_address(12348591, ip, now, 300, 3).unwrap());
+
+        // and the others are once the window is over.
+        let later = now + Duration::from_secs(301);
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 4));
+        assert!(mem.record_ip_address(12348591, ip, later, 300, 3).unwrap());
+        assert_eq!(mem.unique_ip_address_count(12348591), 1);
+    }
+
+    #[test]
+    fn test_record() {
+        let mem = Memory::new();
+        let now = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+
+        assert_eq!(
+            mem.record(
                 12348591,
-                IpAndPath::from_ip_addr(Ipv4Addr::new(127, 0, 0, 1))
+                IpAndPath::new(None, None),
+                now,
+                Windows::new(300),
+                None
             )
-            .is_ok());
-        assert!(mem
-            .insert(
+            .unwrap()
+            .requests,
+            1
+        );
+        assert_eq!(mem.request_count(12348591), 1);
+
+        // everything older than the window is evicted.
+        let later = now + Duration::from_secs(600);
+        assert_eq!(
+            mem.record(
                 12348591,
-                IpAndPath::from_ip_addr(Ipv4Addr::new(127, 0, 0, 1))
+                IpAndPath::new(None, None),
+                later,
+                Windows::new(300),
+                None
             )
-            .is_ok());
-        assert!(mem
-            .insert(
-                12348591,
-                IpAndPath::from_ip_addr(Ipv4Addr::new(127, 0, 0, 1))
+            .unwrap()
+            .requests,
+            1
+        );
+    }
+
+    #[test]
+    fn test_record_within_limits() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let limits = Counts {
+            requests: 3,
+            ip: 2,
+            path: usize::MAX,
+        };
+
+        for _ in 0..2 {
+            mem.record(
+                1,
+                IpAndPath::from_ip_addr(ip),
+                now,
+                Windows::new(300),
+                Some(limits),
             )
-            .is_ok());
-        assert!(mem
-            .insert(
+            .unwrap();
+        }
+
+        // the request over the limit is counted, but not kept.
+        let counts = mem
+            .record(
+                1,
+                IpAndPath::from_ip_addr(ip),
+                now,
+                Windows::new(300),
+                Some(limits),
+            )
+            .unwrap();
+        assert_eq!((counts.requests, counts.ip), (3, 3));
+        assert_eq!(mem.request_count(1), 2);
+
+        let counts = mem
+            .record(
+                1,
+                IpAndPath::new(None, None),
+                now,
+                Windows::new(300),
+                Some(limits),
+            )
+            .unwrap();
+        assert_eq!(counts.requests, 3);
+        assert_eq!(mem.request_count(1), 3);
+    }
+
+    #[test]
+    fn test_record_cost() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let limits = Counts {
+            requests: 1000,
+            ip: usize::MAX,
+            path: usize::MAX,
+        };
+        let request = |cost| IpAndPath::from_ip_addr(ip).with_cost(Some(cost));
+
+        let counts = mem
+            .record(1, request(600), now, Windows::new(300), Some(limits))
+            .unwrap();
+        assert_eq!((counts.requests, counts.ip), (600, 600));
+
+        // a request is kept once, whatever it costs.
+        let counts = mem
+            .record(1, request(500), now, Windows::new(300), Some(limits))
+            .unwrap();
+        assert_eq!(counts.requests, 1100);
+        assert_eq!(mem.request_count(1), 600);
+        assert_eq!(
+            mem.record.read().unwrap()[&1].value.lock().unwrap().len(),
+            1
+        );
+
+        let counts = mem
+            .peek_record(1, request(400), now, Windows::new(300))
+            .unwrap();
+        assert_eq!(counts.requests, 1000);
+        let usage = mem.usage(1, now, Windows::new(300)).unwrap();
+        assert_eq!(usage.requests, 600);
+        assert_eq!(usage.ip_addresses[&ip], 600);
+
+        // the whole cost leaves the window at once.
+        let later = now + Duration::from_secs(301);
+        let counts = mem
+            .record(1, request(1), later, Windows::new(300), Some(limits))
+            .unwrap();
+        assert_eq!(counts.requests, 1);
+    }
+
+    #[test]
+    fn test_windows() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let windows = Windows {
+            id: 300,
+            ip: 10,
+            path: 300,
+        };
+
+        for _ in 0..3 {
+            mem.record(12348591, IpAndPath::from_ip_addr(ip), now, windows, None)
+                .unwrap();
+        }
+
+        let later = now + Duration::from_secs(11);
+        let counts = mem
+            .record(12348591, IpAndPath::from_ip_addr(ip), later, windows, None)
+            .unwrap();
+        assert_eq!(counts.requests, 4);
+        assert_eq!(counts.ip, 1);
+        assert_eq!(counts.path, 0);
+    }
+
+    #[test]
+    fn test_same_timestamp() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+
+        for i in 1..=5 {
+            assert_eq!(
+                mem.record(
+                    12348591,
+                    IpAndPath::new(None, None),
+                    now,
+                    Windows::new(300),
+                    None
+                )
+                .unwrap()
+                .requests,
+                i
+            );
+        }
+
+        // a clock going backwards doesn't reorder the log.
+        let earlier = now - Duration::from_secs(10);
+        assert_eq!(
+            mem.record(
                 12348591,
-                IpAndPath::from_ip_addr(Ipv4Addr::new(127, 0, 0, 1))
+                IpAndPath::new(None, None),
+                earlier,
+                Windows::new(300),
+                None
             )
-            .is_ok());
-        assert!(mem
-            .insert(
+            .unwrap()
+            .requests,
+            6
+        );
+
+        let later = now + Duration::from_secs(301);
+        assert_eq!(
+            mem.record(
                 12348591,
-                IpAndPath::from_ip_addr(Ipv4Addr::new(127, 0, 0, 1))
+                IpAndPath::new(None, None),
+                later,
+                Windows::new(300),
+                None
+            )
+            .unwrap()
+            .requests,
+            1
+        );
+    }
+
+    #[test]
+    fn test_concurrent_ids() {
+        let mem = std::sync::Arc::new(Memory::new());
+
+        let handles: Vec<_> = (0..8)
+            .map(|id| {
+                let mem = mem.clone();
+                std::thread::spawn(move || {
+                    let now = Duration::from_secs(1000);
+                    for i in 0..100 {
+                        let timestamp = now + Duration::from_millis(i);
+                        mem.record(
+                            id,
+                            IpAndPath::new(None, None),
+                            timestamp,
+                            Windows::new(300),
+                            None,
+                        )
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(mem.len(), 8);
+        for id in 0..8 {
+            assert_eq!(mem.request_count(id), 100);
+        }
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let rate = Rate::new(Algorithm::FixedWindow, 2, Duration::from_secs(10));
+
+        mem.record(1, IpAndPath::new(None, None), now, Windows::new(300), None)
+            .unwrap();
+        mem.record(
+            2,
+            IpAndPath::new(None, None),
+            now + Duration::from_secs(200),
+            Windows::new(300),
+            None,
+        )
+        .unwrap();
+        mem.acquire("3".to_owned(), now, rate).unwrap();
+
+        assert_eq!(mem.sweep(now, 300), 0);
+        assert_eq!(mem.len(), 3);
+
+        // the log of 1 and the state of 3 are expired.
+        assert_eq!(mem.sweep(now + Duration::from_secs(301), 300), 2);
+        assert_eq!(mem.len(), 1);
+        assert_eq!(mem.request_count(2), 1);
+    }
+
+    #[test]
+    fn test_max_keys() {
+        let mem = Memory::new().with_max_keys(10);
+        let now = Duration::from_secs(1000);
+
+        for id in 0..10 {
+            let timestamp = now + Duration::from_secs(id);
+            mem.record(
+                id,
+                IpAndPath::new(None, None),
+                timestamp,
+                Windows::new(300),
+                None,
             )
-            .is_ok());
+            .unwrap();
+        }
+        assert_eq!(mem.len(), 10);
+
+        // 0 is used again, so 1 and 2 are the least recently used.
+        mem.record(
+            0,
+            IpAndPath::new(None, None),
+            now + Duration::from_secs(20),
+            Windows::new(300),
+            None,
+        )
+        .unwrap();
+        mem.record(
+            10,
+            IpAndPath::new(None, None),
+            now + Duration::from_secs(21),
+            Windows::new(300),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(mem.len(), 9);
+        assert_eq!(mem.request_count(0), 2);
+        assert_eq!(mem.request_count(1), 0);
+        assert_eq!(mem.request_count(2), 0);
+        assert_eq!(mem.request_count(10), 1);
+    }
+
+    #[test]
+    fn test_max_keys_keeps_counters() {
+        let mem = Memory::new().with_max_keys(10);
+        let now = Duration::from_secs(1000);
+        let expires_at = now + Duration::from_secs(86400);
+
+        mem.add("1:quota:daily".to_owned(), 3, 100, now, expires_at)
+            .unwrap();
+
+        // a flood of new ids and of their counters.
+        for id in 2..100 {
+            let timestamp = now + Duration::from_secs(id);
+            mem.record(
+                id,
+                IpAndPath::new(None, None),
+                timestamp,
+                Windows::new(300),
+                None,
+            )
+            .unwrap();
+            mem.add(format!("{}:quota:daily", id), 1, 100, timestamp, expires_at)
+                .unwrap();
+        }
+
+        let later = now + Duration::from_secs(100);
+        assert_eq!(mem.counter("1:quota:daily".to_owned(), later).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_max_keys_keeps_penalties() {
+        let mem = Memory::new().with_max_keys(10);
+        let now = Duration::from_secs(1000);
+        let window_end = now + Duration::from_secs(3600);
+
+        // the denials of an id, counted the way a penalty does,
+        // in between those of a flood of new ids.
+        for round in 0..3 {
+            for id in 2..50 {
+                let timestamp = now + Duration::from_secs(round * 100 + id);
+                mem.record(
+                    id + round * 100,
+                    IpAndPath::new(None, None),
+                    timestamp,
+                    Windows::new(300),
+                    None,
+                )
+                .unwrap();
+                mem.add(
+                    format!("{}:penalty", id + round * 100),
+                    1,
+                    u64::MAX,
+                    timestamp,
+                    window_end,
+                )
+                .unwrap();
+            }
+
+            let timestamp = now + Duration::from_secs(round * 100 + 50);
+            let denials = mem
+                .add("1:penalty".to_owned(), 1, u64::MAX, timestamp, window_end)
+                .unwrap();
+            assert_eq!(denials, round + 1);
+        }
+    }
+
+    #[test]
+    fn test_max_keys_keeps_leases() {
+        let mem = Memory::new().with_max_keys(10);
+        let now = Duration::from_secs(1000);
+        let expires_at = now + Duration::from_secs(3600);
+
+        for n in 0..2 {
+            let token = format!("1:{}:reports", n);
+            mem.take_lease("1:lease:reports".to_owned(), token, 2, now, expires_at)
+                .unwrap();
+        }
+
+        // new ids fill the map while the leases are held.
+        for id in 2..100 {
+            let timestamp = now + Duration::from_secs(id);
+            mem.take_lease(
+                format!("{}:lease:reports", id),
+                format!("{}:0:reports", id),
+                2,
+                timestamp,
+                expires_at,
+            )
+            .unwrap();
+        }
+
+        let later = now + Duration::from_secs(100);
+        assert_eq!(
+            mem.lease_count("1:lease:reports".to_owned(), later)
+                .unwrap(),
+            2
+        );
+        let token = "1:2:reports".to_owned();
+        assert_eq!(
+            mem.take_lease("1:lease:reports".to_owned(), token, 2, later, expires_at)
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_acquire() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let rate = Rate::new(Algorithm::TokenBucket, 2, Duration::from_secs(2));
+
+        assert!(
+            mem.acquire("12348591".to_owned(), now, rate)
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            mem.acquire("12348591".to_owned(), now, rate)
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            !mem.acquire("12348591".to_owned(), now, rate)
+                .unwrap()
+                .allowed
+        );
+        assert!(mem.acquire("12384".to_owned(), now, rate).unwrap().allowed);
+
+        assert_eq!(mem.len(), 2);
+    }
+
+    #[test]
+    fn test_usage_and_reset() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let rate = Rate::new(Algorithm::FixedWindow, 2, Duration::from_secs(10));
+
+        let requests = vec![
+            (now, IpAndPath::new(Some(ip), Some("/a".to_owned()))),
+            (now + Duration::from_secs(100), IpAndPath::from_ip_addr(ip)),
+            (
+                now + Duration::from_secs(200),
+                IpAndPath::from_path("/a".to_owned()),
+            ),
+        ];
+        for (timestamp, ip_and_path) in requests {
+            mem.record(1, ip_and_path, timestamp, Windows::new(300), None)
+                .unwrap();
+        }
+        mem.acquire("1".to_owned(), now, rate).unwrap();
+        mem.acquire("1:path:/a".to_owned(), now, rate).unwrap();
+        mem.acquire("12".to_owned(), now, rate).unwrap();
+
+        let windows = Windows {
+            id: 150,
+            ip: 300,
+            path: 300,
+        };
+        let usage = mem
+            .usage(1, now + Duration::from_secs(200), windows)
+            .unwrap();
+        assert_eq!(usage.requests, 2);
+        assert_eq!(usage.ip_addresses.get(&ip), Some(&2));
+        assert_eq!(usage.paths.get("/a"), Some(&2));
+
+        // the states of the other ids are kept.
+        mem.reset(1).unwrap();
+        assert_eq!(mem.request_count(1), 0);
+        assert_eq!(mem.len(), 1);
+    }
+
+    #[test]
+    fn test_override() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let limit = Override {
+            limit: 10,
+            window_time: 60,
+        };
+
+        mem.set_override(1, limit, now, Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(mem.get_override(1, now).unwrap(), Some(limit));
+        assert_eq!(mem.get_override(2, now).unwrap(), None);
+
+        let later = now + Duration::from_secs(30);
+        assert_eq!(mem.get_override(1, later).unwrap(), None);
+        assert_eq!(mem.sweep(later, 300), 1);
+    }
+
+    #[test]
+    fn test_add() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let expires_at = now + Duration::from_secs(60);
+
+        assert_eq!(
+            mem.add("1:quota:daily".to_owned(), 2, 3, now, expires_at)
+                .unwrap(),
+            2
+        );
+        // the amount that would go over the limit isn't added.
+        assert_eq!(
+            mem.add("1:quota:daily".to_owned(), 2, 3, now, expires_at)
+                .unwrap(),
+            4
+        );
+        assert_eq!(mem.counter("1:quota:daily".to_owned(), now).unwrap(), 2);
+
+        // the counter starts over once it expires.
+        assert_eq!(
+            mem.counter("1:quota:daily".to_owned(), expires_at).unwrap(),
+            0
+        );
+        let later = expires_at + Duration::from_secs(60);
+        assert_eq!(
+            mem.add("1:quota:daily".to_owned(), 2, 3, expires_at, later)
+                .unwrap(),
+            2
+        );
+
+        mem.reset(1).unwrap();
+        assert_eq!(
+            mem.counter("1:quota:daily".to_owned(), expires_at).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_leases() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let expires_at = now + Duration::from_secs(60);
+        let take = |token: &str, timestamp| {
+            mem.take_lease(
+                "1:lease:reports".to_owned(),
+                token.to_owned(),
+                2,
+                timestamp,
+                timestamp + Duration::from_secs(60),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(take("a", now), 1);
+        assert_eq!(take("b", now), 2);
+        // the lease that would go over the limit isn't taken.
+        assert_eq!(take("c", now), 3);
+        assert_eq!(
+            mem.lease_count("1:lease:reports".to_owned(), now).unwrap(),
+            2
+        );
+
+        assert!(mem
+            .release_lease("1:lease:reports".to_owned(), "a".to_owned(), now)
+            .unwrap());
+        assert!(!mem
+            .release_lease("1:lease:reports".to_owned(), "a".to_owned(), now)
+            .unwrap());
+        assert!(!mem
+            .release_lease("1:lease:reports".to_owned(), "c".to_owned(), now)
+            .unwrap());
+        assert_eq!(take("c", now), 2);
+
+        // the expired leases are reclaimed.
+        assert_eq!(
+            mem.lease_count("1:lease:reports".to_owned(), expires_at)
+                .unwrap(),
+            0
+        );
+        assert!(!mem
+            .release_lease("1:lease:reports".to_owned(), "b".to_owned(), expires_at)
+            .unwrap());
+        assert_eq!(take("d", expires_at), 1);
+        assert_eq!(mem.sweep(expires_at + Duration::from_secs(60), 300), 1);
+
+        take("e", now);
+        mem.reset(1).unwrap();
+        assert_eq!(
+            mem.lease_count("1:lease:reports".to_owned(), now).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_bans() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let ban = Ban {
+            until: (now + Duration::from_secs(60)).as_millis() as u64,
+            offences: 1,
+        };
+
+        mem.set_ban(1, ban, now, now + Duration::from_secs(120))
+            .unwrap();
+        assert_eq!(mem.get_ban(1, now).unwrap(), Some(ban));
+        assert_eq!(mem.bans(now).unwrap(), vec![(1, ban)]);
+
+        // the ban is remembered after it ends, until it expires.
+        let later = now + Duration::from_secs(90);
+        assert_eq!(mem.get_ban(1, later).unwrap(), Some(ban));
+        assert!(mem.bans(later).unwrap().is_empty());
+        assert!(!mem.lift_ban(1, later).unwrap());
+        assert_eq!(mem.get_ban(1, later).unwrap(), None);
+
+        mem.set_ban(2, ban, now, now + Duration::from_secs(120))
+            .unwrap();
+        assert!(mem.lift_ban(2, now).unwrap());
+        assert!(!mem.lift_ban(2, now).unwrap());
+    }
+
+    #[test]
+    fn test_sweep_override() {
+        let mem = Memory::new();
+        let now = Duration::from_secs(1000);
+        let limit = Override {
+            limit: 10,
+            window_time: 600,
+        };
+        mem.set_override(1, limit, now, Duration::from_secs(3600))
+            .unwrap();
+
+        let windows = Windows {
+            id: 600,
+            ..Windows::new(300)
+        };
+        for id in [1, 2] {
+            mem.record(id, IpAndPath::new(None, None), now, windows, None)
+                .unwrap();
+        }
 
-        // assert_eq!(mem.unique_ip_addresses(12348591), 5);
+        // the requests inside the window of the override are kept.
+        let later = now + Duration::from_secs(400);
+        let counts = mem
+            .record(1, IpAndPath::new(None, None), later, windows, None)
+            .unwrap();
+        assert_eq!(counts.requests, 2);
+        assert_eq!(mem.sweep(later, 300), 1);
+        assert_eq!(mem.request_count(1), 2);
+        assert_eq!(mem.request_count(2), 0);
     }
 }
@@ -1,6 +1,11 @@
+#[allow(clippy::module_inception)]
 mod backend;
 mod memory;
+mod redis;
+mod sharded;
 
-pub use backend::Backend;
+pub use self::redis::Redis;
+pub use backend::{Backend, BackendKind, Ban, Counts, Override, Usage, Windows};
 pub use memory::IpAndPath;
 pub use memory::Memory;
+pub use sharded::ShardedMemory;
@@ -0,0 +1,1173 @@
+use std::{error::Error, net::IpAddr, time::Duration};
+
+use redis::{Commands, ErrorKind, RedisError, Script};
+
+use crate::{
+    algorithm::{Outcome, Rate, State},
+    Backend, Ban, Counts, IpAndPath, Override, Usage, Windows,
+};
+
+// Adds up the costs of the requests in a log since the given
+// timestamp. Every request is a single member ending with its
+// cost, members without one cost one.
+const WEIGHT: &str = r"
+local function weight(key, since)
+    local total = 0
+    for _, member in ipairs(redis.call('ZRANGEBYSCORE', key, since, '+inf')) do
+        total = total + (tonumber(string.match(member, '^%d+:%d+:(%d+)$')) or 1)
+    end
+    return total
+end
+";
+
+// Records a request in the sliding window log of an id.
+// Every log is a sorted set scored by the timestamp of the
+// request, so inserting, evicting, counting and dropping the
+// request again if it went over a limit are done in a single
+// atomic step.
+//
+// KEYS[1]   the log of the id
+// KEYS[2]   the sequence of the id, keeps the entries unique
+// KEYS[3..] the logs of the ip address and the path, if any
+// ARGV[1]   the timestamp of the request, in milliseconds
+// ARGV[2]   the cost of the request
+// ARGV[3..] the window time in milliseconds and the limit of
+//           every log, the log of the id first. A negative
+//           limit keeps the request whatever the count is.
+//
+// Returns the cost of the requests in every log.
+const RECORD: &str = r"
+local timestamp = tonumber(ARGV[1])
+local member = ARGV[1] .. ':' .. redis.call('INCR', KEYS[2]) .. ':' .. ARGV[2]
+local logs = {}
+local counts = {}
+local longest = 0
+local exceeded = false
+
+for i, key in ipairs(KEYS) do
+    if i ~= 2 then
+        logs[#logs + 1] = key
+    end
+end
+
+for i, key in ipairs(logs) do
+    local window = tonumber(ARGV[2 * i + 1])
+    local limit = tonumber(ARGV[2 * i + 2])
+    redis.call('ZADD', key, timestamp, member)
+    redis.call('ZREMRANGEBYSCORE', key, '-inf', '(' .. (timestamp - window))
+    redis.call('PEXPIRE', key, window + 1000)
+    counts[i] = weight(key, '-inf')
+    exceeded = exceeded or (limit >= 0 and counts[i] > limit)
+    longest = math.max(longest, window)
+end
+redis.call('PEXPIRE', KEYS[2], longest + 1000)
+
+if exceeded then
+    for _, key in ipairs(logs) do
+        redis.call('ZREM', key, member)
+    end
+end
+
+return counts
+";
+
+// Adds up the costs of the requests in logs.
+//
+// KEYS[i] a log
+// ARGV[i] the timestamp to count the requests from, in milliseconds
+//
+// Returns the cost of the requests in every log.
+const COUNT: &str = r"
+local counts = {}
+for i, key in ipairs(KEYS) do
+    counts[i] = weight(key, ARGV[i])
+end
+
+return counts
+";
+
+// Records the ip address an id sent a request from, unless
+// it already sent requests from too many other addresses.
+//
+// KEYS[1] the ip addresses of the id
+// ARGV[1] the timestamp of the request, in milliseconds
+// ARGV[2] the window time, in milliseconds
+// ARGV[3] the ip address
+// ARGV[4] the maximum number of ip addresses
+//
+// Returns 1 if the address was recorded, 0 otherwise.
+const RECORD_IP_ADDRESS: &str = r"
+local timestamp = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', '(' .. (timestamp - window))
+if not redis.call('ZSCORE', KEYS[1], ARGV[3])
+    and redis.call('ZCARD', KEYS[1]) >= tonumber(ARGV[4]) then
+    return 0
+end
+
+redis.call('ZADD', KEYS[1], timestamp, ARGV[3])
+redis.call('PEXPIRE', KEYS[1], window + 1000)
+
+return 1
+";
+
+// Adds an amount to a counter, unless it would go over the limit.
+//
+// KEYS[1] the counter
+// ARGV[1] the amount
+// ARGV[2] the limit
+// ARGV[3] when the counter expires, in unix milliseconds
+//
+// Returns the counter with the amount, whether it was added or not.
+const ADD: &str = r"
+local count = tonumber(redis.call('GET', KEYS[1]) or '0') + tonumber(ARGV[1])
+if count <= tonumber(ARGV[2]) then
+    redis.call('SET', KEYS[1], count)
+    redis.call('PEXPIREAT', KEYS[1], ARGV[3])
+end
+
+return count
+";
+
+// Takes an amount back from a counter, unless it expired.
+// DECRBY keeps the expire time of the counter.
+//
+// KEYS[1] the counter
+// ARGV[1] the amount
+const SUBTRACT: &str = r"
+local count = tonumber(redis.call('GET', KEYS[1]) or '0')
+if count > 0 then
+    redis.call('DECRBY', KEYS[1], math.min(count, tonumber(ARGV[1])))
+end
+";
+
+// Takes a lease, unless the limit of leases is already held.
+// The leases are a sorted set of tokens scored by when they
+// expire, so the expired ones are reclaimed by their scores.
+//
+// KEYS[1] the leases
+// ARGV[1] the token
+// ARGV[2] the limit
+// ARGV[3] the current time, in unix milliseconds
+// ARGV[4] when the lease expires, in unix milliseconds
+//
+// Returns the number of leases with the new one, whether it was taken or not.
+const TAKE_LEASE: &str = r"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[3])
+local count = redis.call('ZCARD', KEYS[1]) + 1
+if count <= tonumber(ARGV[2]) then
+    redis.call('ZADD', KEYS[1], ARGV[4], ARGV[1])
+    if redis.call('PTTL', KEYS[1]) < tonumber(ARGV[4]) - tonumber(ARGV[3]) then
+        redis.call('PEXPIREAT', KEYS[1], ARGV[4])
+    end
+end
+
+return count
+";
+
+// Redis backend for dur, lets multiple dur instances
+// share the same limits.
+pub struct Redis {
+    pool: r2d2::Pool<redis::Client>,
+    prefix: String,
+    record: Script,
+    count: Script,
+    record_ip_address: Script,
+    add: Script,
+    subtract: Script,
+    take_lease: Script,
+}
+
+impl Redis {
+    // Connections are opened lazily, so this only
+    // fails when the url is invalid.
+    pub fn open(url: &str) -> Result<Self, Box<dyn Error>> {
+        let client = redis::Client::open(url)?;
+
+        Ok(Self {
+            pool: r2d2::Pool::builder().build_unchecked(client),
+            prefix: "dur".to_owned(),
+            record: Script::new(&format!("{}{}", WEIGHT, RECORD)),
+            count: Script::new(&format!("{}{}", WEIGHT, COUNT)),
+            record_ip_address: Script::new(RECORD_IP_ADDRESS),
+            add: Script::new(ADD),
+            subtract: Script::new(SUBTRACT),
+            take_lease: Script::new(TAKE_LEASE),
+        })
+    }
+
+    // Every key is prefixed with "dur" by default.
+    #[allow(dead_code)]
+    pub fn with_prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn log_key(&self, id: u64) -> String {
+        format!("{}:log:{}", self.prefix, id)
+    }
+
+    fn sequence_key(&self, id: u64) -> String {
+        format!("{}:seq:{}", self.prefix, id)
+    }
+
+    fn ip_address_key(&self, id: u64, ip: IpAddr) -> String {
+        format!("{}{}", self.ip_address_prefix(id), ip)
+    }
+
+    fn ip_address_prefix(&self, id: u64) -> String {
+        format!("{}:log:{}:ip:", self.prefix, id)
+    }
+
+    fn path_key(&self, id: u64, path: &str) -> String {
+        format!("{}{}", self.path_prefix(id), path)
+    }
+
+    fn path_prefix(&self, id: u64) -> String {
+        format!("{}:log:{}:path:", self.prefix, id)
+    }
+
+    fn ip_addresses_key(&self, id: u64) -> String {
+        format!("{}:ips:{}", self.prefix, id)
+    }
+
+    fn state_key(&self, key: &str) -> String {
+        format!("{}:state:{}", self.prefix, key)
+    }
+
+    fn counter_key(&self, key: &str) -> String {
+        format!("{}:counter:{}", self.prefix, key)
+    }
+
+    fn leases_key(&self, key: &str) -> String {
+        format!("{}:leases:{}", self.prefix, key)
+    }
+
+    fn override_key(&self, id: u64) -> String {
+        format!("{}:override:{}", self.prefix, id)
+    }
+
+    fn ban_key(&self, id: u64) -> String {
+        format!("{}{}", self.ban_prefix(), id)
+    }
+
+    fn ban_prefix(&self) -> String {
+        format!("{}:ban:", self.prefix)
+    }
+
+    fn keys(&self, pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let keys = con.scan_match(pattern)?.collect();
+
+        Ok(keys)
+    }
+
+    // The cost of the requests in every log since its timestamp.
+    fn weights(&self, logs: Vec<(String, u64)>) -> Result<Vec<usize>, Box<dyn Error>> {
+        if logs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut con = self.pool.get()?;
+        let mut invocation = self.count.prepare_invoke();
+        for (key, since) in logs {
+            invocation.key(key).arg(since);
+        }
+
+        Ok(invocation.invoke(&mut *con)?)
+    }
+
+    fn count(&self, key: String) -> usize {
+        let count = self
+            .weights(vec![(key, 0)])
+            .map(|counts| counts.into_iter().next().unwrap_or_default());
+
+        count.unwrap_or_else(|why| {
+            eprintln!("an error occured: {}", why);
+            0
+        })
+    }
+}
+
+impl Backend for Redis {
+    fn clear(&self) {
+        let cleared = self.keys(&format!("{}:*", self.prefix)).and_then(|keys| {
+            if !keys.is_empty() {
+                let mut con = self.pool.get()?;
+                con.del::<_, ()>(keys)?;
+            }
+
+            Ok(())
+        });
+
+        if let Err(why) = cleared {
+            eprintln!("an error occured: {}", why);
+        }
+    }
+
+    fn record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+        limits: Option<Counts>,
+    ) -> Result<Counts, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let limit = |limit: fn(&Counts) -> usize| match limits {
+            Some(ref limits) => limit(limits).min(i64::MAX as usize) as i64,
+            None => -1,
+        };
+
+        let mut invocation = self.record.prepare_invoke();
+        invocation
+            .key(self.log_key(id))
+            .key(self.sequence_key(id))
+            .arg(timestamp.as_millis() as u64)
+            .arg(ip_and_path.cost())
+            .arg(windows.id as u64 * 1000)
+            .arg(limit(|limits| limits.requests));
+        if let Some(ip) = ip_and_path.ip {
+            invocation
+                .key(self.ip_address_key(id, ip))
+                .arg(windows.ip as u64 * 1000)
+                .arg(limit(|limits| limits.ip));
+        }
+        if let Some(ref path) = ip_and_path.path {
+            invocation
+                .key(self.path_key(id, path))
+                .arg(windows.path as u64 * 1000)
+                .arg(limit(|limits| limits.path));
+        }
+
+        let counts: Vec<usize> = invocation.invoke(&mut *con)?;
+        let mut counts = counts.into_iter();
+
+        Ok(Counts {
+            requests: counts.next().unwrap_or_default(),
+            ip: ip_and_path
+                .ip
+                .and_then(|_| counts.next())
+                .unwrap_or_default(),
+            path: ip_and_path
+                .path
+                .and_then(|_| counts.next())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn record_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+
+        let recorded: u8 = self
+            .record_ip_address
+            .key(self.ip_addresses_key(id))
+            .arg(timestamp.as_millis() as u64)
+            .arg(window_time as u64 * 1000)
+            .arg(ip.to_string())
+            .arg(limit)
+            .invoke(&mut *con)?;
+
+        Ok(recorded == 1)
+    }
+
+    // The requests inside the windows, counting the
+    // request as if it was recorded.
+    fn peek_record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Counts, Box<dyn Error>> {
+        let timestamp = timestamp.as_millis() as u64;
+        let since = |window_time: u16| timestamp.saturating_sub(window_time as u64 * 1000);
+        let cost = ip_and_path.cost();
+
+        let mut logs = vec![(self.log_key(id), since(windows.id))];
+        if let Some(ip) = ip_and_path.ip {
+            logs.push((self.ip_address_key(id, ip), since(windows.ip)));
+        }
+        if let Some(ref path) = ip_and_path.path {
+            logs.push((self.path_key(id, path), since(windows.path)));
+        }
+        let mut counts = self.weights(logs)?.into_iter().map(|count| count + cost);
+
+        Ok(Counts {
+            requests: counts.next().unwrap_or(cost),
+            ip: ip_and_path
+                .ip
+                .and_then(|_| counts.next())
+                .unwrap_or_default(),
+            path: ip_and_path
+                .path
+                .and_then(|_| counts.next())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn peek_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        let key = self.ip_addresses_key(id);
+        let since = (timestamp.as_millis() as u64).saturating_sub(window_time as u64 * 1000);
+        let mut con = self.pool.get()?;
+
+        let seen: Option<u64> = con.zscore(&key, ip.to_string())?;
+        if seen.is_some_and(|seen| seen >= since) {
+            return Ok(true);
+        }
+
+        let count: usize = con.zcount(&key, since, "+inf")?;
+
+        Ok(count < limit)
+    }
+
+    // The number of ids with a log, and the number of states.
+    fn len(&self) -> usize {
+        let len = self
+            .keys(&format!("{}:seq:*", self.prefix))
+            .and_then(|ids| Ok(ids.len() + self.keys(&format!("{}:state:*", self.prefix))?.len()));
+
+        len.unwrap_or_else(|why| {
+            eprintln!("an error occured: {}", why);
+            0
+        })
+    }
+
+    fn is_blocking(&self) -> bool {
+        true
+    }
+
+    fn request_count(&self, id: u64) -> usize {
+        self.count(self.log_key(id))
+    }
+
+    fn ip_address_count(&self, id: u64, ip: IpAddr) -> usize {
+        self.count(self.ip_address_key(id, ip))
+    }
+
+    fn unique_ip_address_count(&self, id: u64) -> usize {
+        let count = self
+            .pool
+            .get()
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|mut con| Ok(con.zcard(self.ip_addresses_key(id))?));
+
+        count.unwrap_or_else(|why| {
+            eprintln!("an error occured: {}", why);
+            0
+        })
+    }
+
+    fn path_count(&self, id: u64, path: String) -> usize {
+        self.count(self.path_key(id, &path))
+    }
+
+    // States are stored as JSON and updated in an optimistic
+    // transaction, which is retried if another instance updates
+    // the same state in the meantime.
+    fn acquire(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let key = self.state_key(&key);
+        // redis rejects expire times that overflow
+        // once they are added to the current time.
+        let expiry = rate.expiry().as_millis().min(i64::MAX as u128 / 2) as usize;
+        let mut con = self.pool.get()?;
+
+        let outcome = redis::transaction(&mut *con, &[&key], |con, pipe| {
+            let stored: Option<String> = con.get(&key)?;
+            let mut state = match stored.and_then(|s| serde_json::from_str::<State>(&s).ok()) {
+                Some(state) if state.algorithm() == rate.algorithm => state,
+                _ => State::new(timestamp, &rate),
+            };
+
+            let outcome = state.acquire(timestamp, &rate);
+            let value = serde_json::to_string(&state).map_err(|why| {
+                RedisError::from((ErrorKind::TypeError, "invalid state", why.to_string()))
+            })?;
+
+            let stored: Option<()> = pipe.pset_ex(&key, value, expiry).ignore().query(con)?;
+
+            Ok(stored.map(|_| outcome))
+        })?;
+
+        Ok(outcome)
+    }
+
+    fn peek(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+
+        let stored: Option<String> = con.get(self.state_key(&key))?;
+        let mut state = match stored.and_then(|s| serde_json::from_str::<State>(&s).ok()) {
+            Some(state) if state.algorithm() == rate.algorithm => state,
+            _ => State::new(timestamp, &rate),
+        };
+
+        Ok(state.acquire(timestamp, &rate))
+    }
+
+    fn release(&self, key: String, timestamp: Duration, rate: Rate) -> Result<(), Box<dyn Error>> {
+        let key = self.state_key(&key);
+        let mut con = self.pool.get()?;
+
+        redis::transaction(&mut *con, &[&key], |con, pipe| {
+            let stored: Option<String> = con.get(&key)?;
+            let mut state = match stored.and_then(|s| serde_json::from_str::<State>(&s).ok()) {
+                Some(state) if state.algorithm() == rate.algorithm => state,
+                _ => return Ok(Some(())),
+            };
+
+            state.release(timestamp, &rate);
+            let value = serde_json::to_string(&state).map_err(|why| {
+                RedisError::from((ErrorKind::TypeError, "invalid state", why.to_string()))
+            })?;
+
+            pipe.set_options(
+                &key,
+                value,
+                redis::SetOptions::default()
+                    .conditional_set(redis::ExistenceCheck::XX)
+                    .with_expiration(redis::SetExpiry::KEEPTTL),
+            )
+            .ignore()
+            .query(con)
+        })?;
+
+        Ok(())
+    }
+
+    fn add(
+        &self,
+        key: String,
+        amount: u64,
+        limit: u64,
+        _timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+
+        let count = self
+            .add
+            .key(self.counter_key(&key))
+            .arg(amount)
+            .arg(limit)
+            .arg(expires_at.as_millis() as u64)
+            .invoke(&mut *con)?;
+
+        Ok(count)
+    }
+
+    // Expired counters are dropped by redis.
+    fn counter(&self, key: String, _timestamp: Duration) -> Result<u64, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let count: Option<u64> = con.get(self.counter_key(&key))?;
+
+        Ok(count.unwrap_or_default())
+    }
+
+    fn subtract(
+        &self,
+        key: String,
+        amount: u64,
+        _timestamp: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+
+        self.subtract
+            .key(self.counter_key(&key))
+            .arg(amount)
+            .invoke::<()>(&mut *con)?;
+
+        Ok(())
+    }
+
+    fn take_lease(
+        &self,
+        key: String,
+        token: String,
+        limit: usize,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+
+        let count = self
+            .take_lease
+            .key(self.leases_key(&key))
+            .arg(token)
+            .arg(limit)
+            .arg(timestamp.as_millis() as u64)
+            .arg(expires_at.as_millis() as u64)
+            .invoke(&mut *con)?;
+
+        Ok(count)
+    }
+
+    fn release_lease(
+        &self,
+        key: String,
+        token: String,
+        timestamp: Duration,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let key = self.leases_key(&key);
+
+        let (expires_at,): (Option<f64>,) = redis::pipe()
+            .atomic()
+            .zscore(&key, &token)
+            .zrem(&key, &token)
+            .ignore()
+            .query(&mut *con)?;
+
+        Ok(matches!(expires_at, Some(expires_at) if expires_at > timestamp.as_millis() as f64))
+    }
+
+    fn lease_count(&self, key: String, timestamp: Duration) -> Result<usize, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let since = format!("({}", timestamp.as_millis());
+
+        Ok(con.zcount(self.leases_key(&key), since, "+inf")?)
+    }
+
+    // The logs of the ip addresses and the paths of the id are
+    // found by their keys, and counted inside their windows.
+    fn usage(
+        &self,
+        id: u64,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Usage, Box<dyn Error>> {
+        let timestamp = timestamp.as_millis() as u64;
+        let since = |window_time: u16| timestamp.saturating_sub(window_time as u64 * 1000);
+        let ip_prefix = self.ip_address_prefix(id);
+        let path_prefix = self.path_prefix(id);
+
+        let ip_keys: Vec<(String, IpAddr)> = self
+            .keys(&format!("{}*", ip_prefix))?
+            .into_iter()
+            .filter_map(|key| {
+                let ip = key[ip_prefix.len()..].parse().ok()?;
+                Some((key, ip))
+            })
+            .collect();
+        let path_keys = self.keys(&format!("{}*", path_prefix))?;
+
+        let mut logs = vec![(self.log_key(id), since(windows.id))];
+        logs.extend(
+            ip_keys
+                .iter()
+                .map(|(key, _)| (key.clone(), since(windows.ip))),
+        );
+        logs.extend(
+            path_keys
+                .iter()
+                .map(|key| (key.clone(), since(windows.path))),
+        );
+        let mut counts = self.weights(logs)?.into_iter();
+
+        let mut usage = Usage {
+            requests: counts.next().unwrap_or_default(),
+            ..Usage::default()
+        };
+        for ((_, ip), count) in ip_keys.into_iter().zip(counts.by_ref()) {
+            usage.ip_addresses.insert(ip, count);
+        }
+        for (key, count) in path_keys.into_iter().zip(counts) {
+            usage
+                .paths
+                .insert(key[path_prefix.len()..].to_owned(), count);
+        }
+
+        Ok(usage)
+    }
+
+    fn reset(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        let mut keys = vec![
+            self.log_key(id),
+            self.sequence_key(id),
+            self.ip_addresses_key(id),
+            self.state_key(&id.to_string()),
+            self.ban_key(id),
+        ];
+        keys.extend(self.keys(&format!("{}:*", self.log_key(id)))?);
+        keys.extend(self.keys(&format!("{}:*", self.state_key(&id.to_string())))?);
+        keys.extend(self.keys(&format!("{}:*", self.counter_key(&id.to_string())))?);
+        keys.extend(self.keys(&format!("{}:*", self.leases_key(&id.to_string())))?);
+
+        let mut con = self.pool.get()?;
+        con.del::<_, ()>(keys)?;
+
+        Ok(())
+    }
+
+    fn set_override(
+        &self,
+        id: u64,
+        limit: Override,
+        _timestamp: Duration,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        con.pset_ex::<_, _, ()>(
+            self.override_key(id),
+            serde_json::to_string(&limit)?,
+            ttl.as_millis().max(1) as usize,
+        )?;
+
+        Ok(())
+    }
+
+    fn get_override(
+        &self,
+        id: u64,
+        _timestamp: Duration,
+    ) -> Result<Option<Override>, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let stored: Option<String> = con.get(self.override_key(id))?;
+
+        Ok(match stored {
+            Some(stored) => Some(serde_json::from_str(&stored)?),
+            None => None,
+        })
+    }
+
+    fn set_ban(
+        &self,
+        id: u64,
+        ban: Ban,
+        _timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        redis::pipe()
+            .atomic()
+            .set(self.ban_key(id), serde_json::to_string(&ban)?)
+            .ignore()
+            .pexpire_at(self.ban_key(id), expires_at.as_millis() as usize)
+            .ignore()
+            .query::<()>(&mut *con)?;
+
+        Ok(())
+    }
+
+    // Expired bans are dropped by redis.
+    fn get_ban(&self, id: u64, _timestamp: Duration) -> Result<Option<Ban>, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let stored: Option<String> = con.get(self.ban_key(id))?;
+
+        Ok(match stored {
+            Some(stored) => Some(serde_json::from_str(&stored)?),
+            None => None,
+        })
+    }
+
+    fn bans(&self, timestamp: Duration) -> Result<Vec<(u64, Ban)>, Box<dyn Error>> {
+        let prefix = self.ban_prefix();
+        let mut bans = Vec::new();
+
+        for key in self.keys(&format!("{}*", prefix))? {
+            if let Ok(id) = key[prefix.len()..].parse() {
+                match self.get_ban(id, timestamp)? {
+                    Some(ban) if ban.is_active(timestamp) => bans.push((id, ban)),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(bans)
+    }
+
+    fn lift_ban(&self, id: u64, timestamp: Duration) -> Result<bool, Box<dyn Error>> {
+        let mut con = self.pool.get()?;
+        let (stored,): (Option<String>,) = redis::pipe()
+            .atomic()
+            .get(self.ban_key(id))
+            .del(self.ban_key(id))
+            .ignore()
+            .query(&mut *con)?;
+
+        Ok(match stored {
+            Some(stored) => serde_json::from_str::<Ban>(&stored)?.is_active(timestamp),
+            None => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::SystemTime};
+
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    // These tests need a running redis-server, run them with
+    // `DUR_REDIS_URL=redis://127.0.0.1/ cargo test -- --ignored`.
+    fn redis(prefix: &str) -> Redis {
+        let url = std::env::var("DUR_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".into());
+        let redis = Redis::open(&url).unwrap().with_prefix(prefix);
+        redis.clear();
+
+        redis
+    }
+
+    fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_record() {
+        let redis = redis("dur-test-record");
+        let now = now();
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+
+        let windows = Windows::new(300);
+
+        for count in 1..=3 {
+            let recorded = redis
+                .record(12348591, IpAndPath::from_ip_addr(ip), now, windows, None)
+                .unwrap();
+            assert_eq!(recorded.requests, count);
+            assert_eq!(recorded.ip, count);
+        }
+        redis
+            .record(
+                12348591,
+                IpAndPath::from_path("/abc".to_owned()),
+                now,
+                windows,
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(redis.request_count(12348591), 4);
+        assert_eq!(redis.ip_address_count(12348591, ip), 3);
+        assert_eq!(redis.path_count(12348591, "/abc".to_owned()), 1);
+        assert_eq!(redis.len(), 1);
+
+        // everything older than the window is evicted.
+        let later = now + Duration::from_secs(600);
+        let recorded = redis
+            .record(12348591, IpAndPath::new(None, None), later, windows, None)
+            .unwrap();
+        assert_eq!(recorded.requests, 1);
+
+        // the path has its own window.
+        let windows = Windows {
+            path: 10,
+            ..windows
+        };
+        let path = IpAndPath::from_path("/abc".to_owned());
+        redis
+            .record(12348591, path.clone(), later, windows, None)
+            .unwrap();
+        let recorded = redis
+            .record(
+                12348591,
+                path,
+                later + Duration::from_secs(11),
+                windows,
+                None,
+            )
+            .unwrap();
+        assert_eq!(recorded.requests, 3);
+        assert_eq!(recorded.path, 1);
+
+        redis.clear();
+        assert_eq!(redis.len(), 0);
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_record_within_limits() {
+        let redis = redis("dur-test-record-within-limits");
+        let now = now();
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let limits = Counts {
+            requests: 3,
+            ip: 2,
+            path: usize::MAX,
+        };
+
+        for _ in 0..2 {
+            redis
+                .record(
+                    1,
+                    IpAndPath::from_ip_addr(ip),
+                    now,
+                    Windows::new(300),
+                    Some(limits),
+                )
+                .unwrap();
+        }
+
+        let counts = redis
+            .record(
+                1,
+                IpAndPath::from_ip_addr(ip),
+                now,
+                Windows::new(300),
+                Some(limits),
+            )
+            .unwrap();
+        assert_eq!((counts.requests, counts.ip), (3, 3));
+        assert_eq!(redis.request_count(1), 2);
+        assert_eq!(redis.ip_address_count(1, ip), 2);
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_record_cost() {
+        let redis = redis("dur-test-record-cost");
+        let now = now();
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let limits = Counts {
+            requests: 1000,
+            ip: usize::MAX,
+            path: usize::MAX,
+        };
+        let request = |cost| IpAndPath::from_ip_addr(ip).with_cost(Some(cost));
+
+        let counts = redis
+            .record(1, request(600), now, Windows::new(300), Some(limits))
+            .unwrap();
+        assert_eq!((counts.requests, counts.ip), (600, 600));
+
+        // a request is kept once, whatever it costs.
+        let counts = redis
+            .record(1, request(500), now, Windows::new(300), Some(limits))
+            .unwrap();
+        assert_eq!(counts.requests, 1100);
+        assert_eq!(redis.request_count(1), 600);
+        let mut con = redis.pool.get().unwrap();
+        assert_eq!(con.zcard::<_, usize>(redis.log_key(1)).unwrap(), 1);
+
+        let counts = redis
+            .peek_record(1, request(400), now, Windows::new(300))
+            .unwrap();
+        assert_eq!(counts.requests, 1000);
+        let usage = redis.usage(1, now, Windows::new(300)).unwrap();
+        assert_eq!(usage.requests, 600);
+        assert_eq!(usage.ip_addresses[&ip], 600);
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_add() {
+        let redis = redis("dur-test-add");
+        let now = now();
+        let expires_at = now + Duration::from_secs(60);
+
+        assert_eq!(
+            redis
+                .add("1:quota:daily".to_owned(), 2, 3, now, expires_at)
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            redis
+                .add("1:quota:daily".to_owned(), 2, 3, now, expires_at)
+                .unwrap(),
+            4
+        );
+        assert_eq!(redis.counter("1:quota:daily".to_owned(), now).unwrap(), 2);
+
+        // the counter never goes below zero.
+        redis.subtract("1:quota:daily".to_owned(), 3, now).unwrap();
+        assert_eq!(redis.counter("1:quota:daily".to_owned(), now).unwrap(), 0);
+        redis.subtract("1:quota:daily".to_owned(), 1, now).unwrap();
+        assert_eq!(redis.counter("1:quota:daily".to_owned(), now).unwrap(), 0);
+
+        redis
+            .add("1:quota:daily".to_owned(), 2, 3, now, expires_at)
+            .unwrap();
+        redis.reset(1).unwrap();
+        assert_eq!(redis.counter("1:quota:daily".to_owned(), now).unwrap(), 0);
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_record_ip_address() {
+        let redis = redis("dur-test-record-ip-address");
+        let now = now();
+
+        for i in 1..=3 {
+            let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, i));
+            assert!(redis.record_ip_address(1, ip, now, 300, 2).unwrap() == (i <= 2));
+        }
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        assert!(redis.record_ip_address(1, ip, now, 300, 2).unwrap());
+        assert_eq!(redis.unique_ip_address_count(1), 2);
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_acquire() {
+        let redis = redis("dur-test-acquire");
+        let now = now();
+        let rate = Rate::new(Algorithm::Gcra, 2, Duration::from_secs(10));
+
+        assert!(redis.acquire("1".to_owned(), now, rate).unwrap().allowed);
+        assert!(redis.acquire("1".to_owned(), now, rate).unwrap().allowed);
+
+        let outcome = redis.acquire("1".to_owned(), now, rate).unwrap();
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.retry_after, Some(Duration::from_secs(5)));
+
+        redis.release("1".to_owned(), now, rate).unwrap();
+        assert!(redis.acquire("1".to_owned(), now, rate).unwrap().allowed);
+        assert!(!redis.acquire("1".to_owned(), now, rate).unwrap().allowed);
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_usage_and_reset() {
+        let redis = redis("dur-test-usage");
+        let now = now();
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let rate = Rate::new(Algorithm::FixedWindow, 2, Duration::from_secs(10));
+
+        let ip_and_path = IpAndPath::new(Some(ip), Some("/a".to_owned()));
+        redis
+            .record(1, ip_and_path.clone(), now, Windows::new(300), None)
+            .unwrap();
+        redis
+            .record(1, ip_and_path, now, Windows::new(300), None)
+            .unwrap();
+        redis.acquire("1:path:/a".to_owned(), now, rate).unwrap();
+
+        let usage = redis.usage(1, now, Windows::new(300)).unwrap();
+        assert_eq!(usage.requests, 2);
+        assert_eq!(usage.ip_addresses.get(&ip), Some(&2));
+        assert_eq!(usage.paths.get("/a"), Some(&2));
+
+        redis.reset(1).unwrap();
+        assert_eq!(
+            redis.usage(1, now, Windows::new(300)).unwrap(),
+            Usage::default()
+        );
+        assert_eq!(redis.len(), 0);
+
+        let limit = Override {
+            limit: 10,
+            window_time: 60,
+        };
+        redis
+            .set_override(1, limit, now, Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(redis.get_override(1, now).unwrap(), Some(limit));
+        assert_eq!(redis.get_override(2, now).unwrap(), None);
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_leases() {
+        let redis = redis("dur-test-leases");
+        let now = now();
+        let expires_at = now + Duration::from_secs(60);
+        let take = |token: &str, timestamp| {
+            redis
+                .take_lease(
+                    "1:lease:reports".to_owned(),
+                    token.to_owned(),
+                    2,
+                    timestamp,
+                    timestamp + Duration::from_secs(60),
+                )
+                .unwrap()
+        };
+
+        assert_eq!(take("a", now), 1);
+        assert_eq!(take("b", now), 2);
+        assert_eq!(take("c", now), 3);
+        assert_eq!(
+            redis
+                .lease_count("1:lease:reports".to_owned(), now)
+                .unwrap(),
+            2
+        );
+
+        assert!(redis
+            .release_lease("1:lease:reports".to_owned(), "a".to_owned(), now)
+            .unwrap());
+        assert!(!redis
+            .release_lease("1:lease:reports".to_owned(), "a".to_owned(), now)
+            .unwrap());
+        assert_eq!(take("c", now), 2);
+
+        // the expired leases are reclaimed.
+        assert_eq!(take("d", expires_at), 1);
+
+        redis.reset(1).unwrap();
+        assert_eq!(
+            redis
+                .lease_count("1:lease:reports".to_owned(), now)
+                .unwrap(),
+            0
+        );
+
+        redis.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a running redis-server"]
+    fn test_bans() {
+        let redis = redis("dur-test-bans");
+        let now = now();
+        let ban = Ban {
+            until: (now + Duration::from_secs(60)).as_millis() as u64,
+            offences: 1,
+        };
+
+        redis
+            .set_ban(1, ban, now, now + Duration::from_secs(120))
+            .unwrap();
+        assert_eq!(redis.get_ban(1, now).unwrap(), Some(ban));
+        assert_eq!(redis.bans(now).unwrap(), vec![(1, ban)]);
+
+        let later = now + Duration::from_secs(90);
+        assert!(redis.bans(later).unwrap().is_empty());
+        assert!(redis.lift_ban(1, now).unwrap());
+        assert!(!redis.lift_ban(1, now).unwrap());
+        assert_eq!(redis.get_ban(1, now).unwrap(), None);
+
+        redis.clear();
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    error::Error,
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    time::Duration,
+};
+
+use super::Memory;
+use crate::{
+    algorithm::{Outcome, Rate},
+    Backend, Ban, Counts, IpAndPath, Override, Usage, Windows,
+};
+
+// In memory backend that partitions the ids across shards.
+// Every shard is locked on its own, so new ids showing up
+// on different shards don't wait for each other either.
+#[derive(Debug)]
+pub struct ShardedMemory {
+    shards: Vec<Memory>,
+}
+
+impl ShardedMemory {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Memory::new()).collect(),
+        }
+    }
+
+    // Caps the number of keys, split evenly across the shards.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        let per_shard = max_keys.div_ceil(self.shards.len());
+        self.shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.with_max_keys(per_shard))
+            .collect();
+        self
+    }
+
+    fn shard<K: Hash + ?Sized>(&self, key: &K) -> &Memory {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+}
+
+impl Backend for ShardedMemory {
+    fn clear(&self) {
+        self.shards.iter().for_each(Memory::clear);
+    }
+
+    fn record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+        limits: Option<Counts>,
+    ) -> Result<Counts, Box<dyn Error>> {
+        self.shard(&id)
+            .record(id, ip_and_path, timestamp, windows, limits)
+    }
+
+    fn record_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        self.shard(&id)
+            .record_ip_address(id, ip, timestamp, window_time, limit)
+    }
+
+    fn peek_record(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Counts, Box<dyn Error>> {
+        self.shard(&id)
+            .peek_record(id, ip_and_path, timestamp, windows)
+    }
+
+    fn peek_ip_address(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        timestamp: Duration,
+        window_time: u16,
+        limit: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        self.shard(&id)
+            .peek_ip_address(id, ip, timestamp, window_time, limit)
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(Memory::len).sum()
+    }
+
+    fn sweep(&self, timestamp: Duration, window_time: u16) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.sweep(timestamp, window_time))
+            .sum()
+    }
+
+    fn request_count(&self, id: u64) -> usize {
+        self.shard(&id).request_count(id)
+    }
+
+    fn ip_address_count(&self, id: u64, ip: IpAddr) -> usize {
+        self.shard(&id).ip_address_count(id, ip)
+    }
+
+    fn path_count(&self, id: u64, path: String) -> usize {
+        self.shard(&id).path_count(id, path)
+    }
+
+    fn unique_ip_address_count(&self, id: u64) -> usize {
+        self.shard(&id).unique_ip_address_count(id)
+    }
+
+    fn acquire(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        self.shard(key.as_str()).acquire(key, timestamp, rate)
+    }
+
+    fn peek(
+        &self,
+        key: String,
+        timestamp: Duration,
+        rate: Rate,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        self.shard(key.as_str()).peek(key, timestamp, rate)
+    }
+
+    fn release(&self, key: String, timestamp: Duration, rate: Rate) -> Result<(), Box<dyn Error>> {
+        self.shard(key.as_str()).release(key, timestamp, rate)
+    }
+
+    fn add(
+        &self,
+        key: String,
+        amount: u64,
+        limit: u64,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<u64, Box<dyn Error>> {
+        self.shard(key.as_str())
+            .add(key, amount, limit, timestamp, expires_at)
+    }
+
+    fn counter(&self, key: String, timestamp: Duration) -> Result<u64, Box<dyn Error>> {
+        self.shard(key.as_str()).counter(key, timestamp)
+    }
+
+    fn subtract(
+        &self,
+        key: String,
+        amount: u64,
+        timestamp: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.shard(key.as_str()).subtract(key, amount, timestamp)
+    }
+
+    fn take_lease(
+        &self,
+        key: String,
+        token: String,
+        limit: usize,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.shard(key.as_str())
+            .take_lease(key, token, limit, timestamp, expires_at)
+    }
+
+    fn release_lease(
+        &self,
+        key: String,
+        token: String,
+        timestamp: Duration,
+    ) -> Result<bool, Box<dyn Error>> {
+        self.shard(key.as_str())
+            .release_lease(key, token, timestamp)
+    }
+
+    fn lease_count(&self, key: String, timestamp: Duration) -> Result<usize, Box<dyn Error>> {
+        self.shard(key.as_str()).lease_count(key, timestamp)
+    }
+
+    fn usage(
+        &self,
+        id: u64,
+        timestamp: Duration,
+        windows: Windows,
+    ) -> Result<Usage, Box<dyn Error>> {
+        self.shard(&id).usage(id, timestamp, windows)
+    }
+
+    // The states of the id are sharded by their
+    // keys, so they can be in any of the shards.
+    fn reset(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.shards.iter().try_for_each(|shard| shard.reset(id))
+    }
+
+    fn set_override(
+        &self,
+        id: u64,
+        limit: Override,
+        timestamp: Duration,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.shard(&id).set_override(id, limit, timestamp, ttl)
+    }
+
+    fn get_override(
+        &self,
+        id: u64,
+        timestamp: Duration,
+    ) -> Result<Option<Override>, Box<dyn Error>> {
+        self.shard(&id).get_override(id, timestamp)
+    }
+
+    fn set_ban(
+        &self,
+        id: u64,
+        ban: Ban,
+        timestamp: Duration,
+        expires_at: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.shard(&id).set_ban(id, ban, timestamp, expires_at)
+    }
+
+    fn get_ban(&self, id: u64, timestamp: Duration) -> Result<Option<Ban>, Box<dyn Error>> {
+        self.shard(&id).get_ban(id, timestamp)
+    }
+
+    fn bans(&self, timestamp: Duration) -> Result<Vec<(u64, Ban)>, Box<dyn Error>> {
+        let mut bans = Vec::new();
+        for shard in &self.shards {
+            bans.extend(shard.bans(timestamp)?);
+        }
+
+        Ok(bans)
+    }
+
+    fn lift_ban(&self, id: u64, timestamp: Duration) -> Result<bool, Box<dyn Error>> {
+        self.shard(&id).lift_ban(id, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+
+    #[test]
+    fn test_ids_across_shards() {
+        let sharded = ShardedMemory::new(4);
+        let now = Duration::from_secs(1000);
+
+        for id in 0..100 {
+            for i in 0..3 {
+                let timestamp = now + Duration::from_millis(i);
+                assert!(sharded
+                    .record(
+                        id,
+                        IpAndPath::new(None, None),
+                        timestamp,
+                        Windows::new(300),
+                        None
+                    )
+                    .is_ok());
+            }
+        }
+
+        assert_eq!(sharded.len(), 100);
+        assert!(sharded.shards.iter().all(|shard| !shard.is_empty()));
+        for id in 0..100 {
+            assert_eq!(sharded.request_count(id), 3);
+        }
+
+        let rate = Rate::new(Algorithm::FixedWindow, 1, Duration::from_secs(10));
+        assert!(sharded.acquire("1".to_owned(), now, rate).unwrap().allowed);
+        assert!(!sharded.acquire("1".to_owned(), now, rate).unwrap().allowed);
+        assert_eq!(sharded.len(), 101);
+
+        sharded.clear();
+        assert!(sharded.is_empty());
+    }
+}
@@ -1,10 +1,11 @@
-use std::{net::Ipv4Addr, str::FromStr};
+use std::{net::IpAddr, str::FromStr};
 
 pub use clap::{App, Arg};
 
 use crate::{
-    config::{Ip, Limits, Path},
-    Config,
+    algorithm::Algorithm,
+    config::{Cidr, Ip, KeyTemplate, Limits, Path, PathPattern, Secret},
+    BackendKind, Config,
 };
 
 static NAME: &str = "dur";
@@ -26,6 +27,17 @@ mod options {
     pub const IP_ADDRESSES: &str = "ip-addresses";
     pub const IP_ADDRESSES_LIMIT: &str = "ip-addresses-limit";
     pub const IP_ADDRESSES_WINDOW_TIME: &str = "ip-addresses-window-time";
+    pub const ALGORITHM: &str = "algorithm";
+    pub const BACKEND: &str = "backend";
+    pub const REDIS_URL: &str = "redis-url";
+    pub const SHARDS: &str = "shards";
+    pub const WORKERS: &str = "workers";
+    pub const SWEEP_INTERVAL: &str = "sweep-interval";
+    pub const MAX_KEYS: &str = "max-keys";
+    pub const IPV6_PREFIX: &str = "ipv6-prefix";
+    pub const KEY: &str = "key";
+    pub const HASH_SECRET: &str = "hash-secret";
+    pub const COUNT_REJECTED: &str = "count-rejected";
 }
 
 pub fn cli() -> Config {
@@ -88,7 +100,7 @@ pub fn cli() -> Config {
             Arg::with_name(options::PATHS)
                 .short("P")
                 .long(options::PATHS)
-                .help("Paths to be specifically limited, with comma seperated values")
+                .help("Paths or path patterns to be specifically limited, with comma seperated values")
                 .value_name("PATH,PATH...")
                 .takes_value(true)
                 .require_delimiter(true),
@@ -114,7 +126,7 @@ pub fn cli() -> Config {
             Arg::with_name(options::IP_ADDRESSES)
                 .short("I")
                 .long(options::IP_ADDRESSES)
-                .help("IP Addresses to be specifically limited, with comma seperated values")
+                .help("IP Addresses or ranges to be specifically limited, with comma seperated values")
                 .value_name("IP,IP...")
                 .takes_value(true)
                 .require_delimiter(true),
@@ -136,6 +148,96 @@ pub fn cli() -> Config {
                 .value_name("INT")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name(options::ALGORITHM)
+                .short("a")
+                .long(options::ALGORITHM)
+                .help("The rate limiting algorithm")
+                .possible_values(Algorithm::VARIANTS)
+                .default_value("sliding_window_log")
+                .value_name("ALGORITHM")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::BACKEND)
+                .long(options::BACKEND)
+                .help("The backend to store the limits in")
+                .possible_values(BackendKind::VARIANTS)
+                .default_value("memory")
+                .value_name("BACKEND")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::REDIS_URL)
+                .long(options::REDIS_URL)
+                .help("The url of the redis server, used by the redis backend")
+                .default_value("redis://127.0.0.1/")
+                .value_name("URL")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::SHARDS)
+                .long(options::SHARDS)
+                .help("The number of shards, used by the sharded memory backend")
+                .default_value("16")
+                .value_name("INT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::WORKERS)
+                .short("w")
+                .long(options::WORKERS)
+                .help("The number of workers, the number of CPUs by default")
+                .value_name("INT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::SWEEP_INTERVAL)
+                .long(options::SWEEP_INTERVAL)
+                .help("How often the idle ids are dropped from memory, in seconds, 0 to never")
+                .default_value("60")
+                .value_name("INT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::MAX_KEYS)
+                .long(options::MAX_KEYS)
+                .help("The maximum number of ids kept in memory, unlimited by default")
+                .value_name("INT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::IPV6_PREFIX)
+                .long(options::IPV6_PREFIX)
+                .help("The prefix length ipv6 addresses are limited by")
+                .default_value("64")
+                .value_name("INT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::COUNT_REJECTED)
+                .long(options::COUNT_REJECTED)
+                .help("Whether the denied requests count against the limits too")
+                .possible_values(&["true", "false"])
+                .default_value("true")
+                .value_name("BOOL")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::KEY)
+                .short("k")
+                .long(options::KEY)
+                .help("The template the key of a request is built from, e.g. {tenant}:{user}")
+                .value_name("TEMPLATE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(options::HASH_SECRET)
+                .long(options::HASH_SECRET)
+                .help("The secret keys are hashed with, shared by the instances of a backend")
+                .value_name("SECRET")
+                .takes_value(true),
+        )
         .get_matches();
 
     let limit = matches
@@ -157,7 +259,12 @@ pub fn cli() -> Config {
     let limits: Limits = {
         let path: Option<Path> = match matches.values_of(options::PATHS) {
             Some(v) => {
-                let vals: Vec<String> = v.map(String::from).collect();
+                let vals: Vec<PathPattern> = v
+                    .map(|s| match PathPattern::from_str(s) {
+                        Ok(pattern) => pattern,
+                        Err(e) => panic!("bad path: {}", e),
+                    })
+                    .collect();
                 let path_window_time = matches
                     .value_of(options::PATH_WINDOW_TIME)
                     .unwrap_or("300")
@@ -177,12 +284,21 @@ pub fn cli() -> Config {
 
         let ip: Option<Ip> = match matches.values_of(options::IP_ADDRESSES) {
             Some(v) => {
-                let vals: Vec<Ipv4Addr> = v
-                    .map(|s| match Ipv4Addr::from_str(s) {
+                let (ranges, addrs): (Vec<&str>, Vec<&str>) = v.partition(|s| s.contains('/'));
+                let vals: Vec<IpAddr> = addrs
+                    .into_iter()
+                    .map(|s| match IpAddr::from_str(s) {
                         Ok(addr) => addr,
                         Err(e) => panic!("bad ip address: {}", e),
                     })
                     .collect();
+                let ranges: Vec<Cidr> = ranges
+                    .into_iter()
+                    .map(|s| match Cidr::from_str(s) {
+                        Ok(range) => range,
+                        Err(e) => panic!("bad ip range: {}", e),
+                    })
+                    .collect();
                 let ip_window_time = matches
                     .value_of(options::IP_ADDRESSES_WINDOW_TIME)
                     .unwrap_or("300")
@@ -195,7 +311,7 @@ pub fn cli() -> Config {
                     .parse::<u32>()
                     .unwrap();
 
-                Some(Ip::new(vals, ip_limit, ip_window_time))
+                Some(Ip::new(vals, ip_limit, ip_window_time).with_ip_ranges(ranges, false))
             }
             None => None,
         };
@@ -203,18 +319,87 @@ pub fn cli() -> Config {
         Limits::new(path, ip)
     };
 
+    let algorithm = matches
+        .value_of(options::ALGORITHM)
+        .unwrap()
+        .parse::<Algorithm>()
+        .unwrap();
+
+    let backend = matches
+        .value_of(options::BACKEND)
+        .unwrap()
+        .parse::<BackendKind>()
+        .unwrap();
+    let redis_url = matches.value_of(options::REDIS_URL).unwrap().to_owned();
+    let shards = matches
+        .value_of(options::SHARDS)
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let workers = matches
+        .value_of(options::WORKERS)
+        .map(|workers| workers.parse::<usize>().unwrap());
+    let sweep_interval = matches
+        .value_of(options::SWEEP_INTERVAL)
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let max_keys = matches
+        .value_of(options::MAX_KEYS)
+        .map(|max_keys| max_keys.parse::<usize>().unwrap());
+    let ipv6_prefix = matches
+        .value_of(options::IPV6_PREFIX)
+        .unwrap()
+        .parse::<u8>()
+        .unwrap();
+    let count_rejected = matches
+        .value_of(options::COUNT_REJECTED)
+        .unwrap()
+        .parse::<bool>()
+        .unwrap();
+
+    let key = matches
+        .value_of(options::KEY)
+        .map(|key| match KeyTemplate::from_str(key) {
+            Ok(key) => key,
+            Err(e) => panic!("bad key: {}", e),
+        });
+
     let port = matches.value_of(options::PORT).unwrap().to_owned();
     let host = matches.value_of(options::HOST).unwrap().to_owned();
 
     match matches.value_of(options::CONFIG_PATH) {
         Some(path) => Config::from_path(path.to_owned()),
-        None => Config::new(
-            Some(limit),
-            Some(ip_addr_limit),
-            Some(window_time),
-            Some(port),
-            Some(host),
-            limits,
-        ),
+        None => {
+            let mut config = Config::new(
+                Some(limit),
+                Some(ip_addr_limit),
+                Some(window_time),
+                Some(port),
+                Some(host),
+                limits,
+            );
+            config.set_algorithm(algorithm);
+            config.set_backend(backend);
+            config.set_redis_url(redis_url);
+            config.set_shards(shards);
+            if let Some(workers) = workers {
+                config.set_workers(workers);
+            }
+            config.set_sweep_interval(sweep_interval);
+            if let Some(max_keys) = max_keys {
+                config.set_max_keys(max_keys);
+            }
+            config.set_ipv6_prefix(ipv6_prefix);
+            config.set_count_rejected(count_rejected);
+            if let Some(key) = key {
+                config.set_key(key);
+            }
+            if let Some(secret) = matches.value_of(options::HASH_SECRET) {
+                config.set_hash_secret(Secret::new(secret));
+            }
+
+            config
+        }
     }
 }
@@ -0,0 +1,128 @@
+use std::{collections::HashSet, net::IpAddr};
+
+use serde::Deserialize;
+
+use super::{Cidr, IpRanges, PathPattern};
+use crate::helpers::KeyHasher;
+
+// The ids, ip addresses and paths whose requests are always let
+// through, or always turned away, without being limited. Written
+// as an `[allow]` or a `[deny]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "AccessTable")]
+pub struct AccessList {
+    // The listed ids or keys, and the ids they are stored
+    // under once they are hashed with the secret of the config.
+    keys: Vec<String>,
+    ids: HashSet<u64>,
+    ranges: IpRanges,
+    paths: Vec<PathPattern>,
+}
+
+// The [allow] and [deny] tables as they are written in the config.
+#[derive(Deserialize)]
+struct AccessTable {
+    // Ids or keys, the same as the ones of the admin routes.
+    #[serde(default)]
+    ids: Vec<String>,
+    // Ip addresses or ranges.
+    #[serde(default)]
+    ips: Vec<Cidr>,
+    #[serde(default)]
+    paths: Vec<PathPattern>,
+}
+
+impl From<AccessTable> for AccessList {
+    fn from(table: AccessTable) -> Self {
+        Self {
+            keys: table.ids,
+            ids: HashSet::new(),
+            ranges: table.ips.into_iter().collect(),
+            paths: table.paths,
+        }
+    }
+}
+
+impl AccessList {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(dead_code)]
+    pub fn with_ids<I, T>(mut self, keys: I) -> Self
+    where
+        T: AsRef<str>,
+        I: IntoIterator<Item = T>,
+    {
+        self.keys
+            .extend(keys.into_iter().map(|key| key.as_ref().to_owned()));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_ips<I>(mut self, ips: I) -> Self
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        for ip in ips {
+            self.ranges.insert(ip);
+        }
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_paths<I, T>(mut self, paths: I) -> Self
+    where
+        T: Into<PathPattern>,
+        I: IntoIterator<Item = T>,
+    {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    // Hashes the listed keys into the ids they are stored under.
+    pub fn hash_ids(&mut self, hasher: &KeyHasher) {
+        self.ids = self.keys.iter().map(|key| hasher.id_of(key)).collect();
+    }
+
+    // Whether the id, the ip address or the path
+    // of a request is on the list.
+    pub fn contains(&self, id: u64, ip: Option<IpAddr>, path: Option<&str>) -> bool {
+        self.ids.contains(&id)
+            || ip.is_some_and(|ip| self.ranges.lookup(ip).is_some())
+            || path.is_some_and(|path| self.paths.iter().any(|pattern| pattern.is_match(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let hasher = KeyHasher::new("secret");
+        let mut list: AccessList = toml::from_str(
+            r#"
+            ids = ["acme", "1234"]
+            ips = ["10.0.0.0/8", "192.168.1.7"]
+            paths = ["/health", "/internal/**"]
+            "#,
+        )
+        .unwrap();
+        assert!(!list.contains(1234, None, None));
+
+        list.hash_ids(&hasher);
+        assert!(list.contains(1234, None, None));
+        assert!(list.contains(hasher.id_of("acme"), None, None));
+        assert!(!list.contains(5678, None, None));
+
+        let ip = |s: &str| Some(s.parse().unwrap());
+        assert!(list.contains(5678, ip("10.27.104.15"), None));
+        assert!(list.contains(5678, ip("192.168.1.7"), None));
+        assert!(!list.contains(5678, ip("192.168.1.8"), None));
+
+        assert!(list.contains(5678, None, Some("/internal/metrics/cpu")));
+        assert!(!list.contains(5678, None, Some("/healthz")));
+    }
+}
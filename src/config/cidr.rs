@@ -0,0 +1,272 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    iter::FromIterator,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+// A range of ip addresses, written as `10.27.0.0/16`.
+// A single address is a range of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    // The bits of the address after the prefix are cleared.
+    pub fn new(ip: IpAddr, prefix: u8) -> Result<Self, String> {
+        let ip = ip.to_canonical();
+        if prefix > width(ip) {
+            return Err(format!("invalid prefix length: {}/{}", ip, prefix));
+        }
+
+        let network = match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+        };
+
+        Ok(Self { network, prefix })
+    }
+
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    #[allow(dead_code)]
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match Cidr::new(ip, self.prefix) {
+            Ok(range) => range.network == self.network,
+            Err(_) => false,
+        }
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(ip: IpAddr) -> Self {
+        let ip = ip.to_canonical();
+
+        Self {
+            network: ip,
+            prefix: width(ip),
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid ip range: {}", s);
+
+        match s.split_once('/') {
+            Some((ip, prefix)) => Cidr::new(
+                ip.parse().map_err(|_| invalid())?,
+                prefix.parse().map_err(|_| invalid())?,
+            ),
+            None => Ok(Cidr::from(s.parse::<IpAddr>().map_err(|_| invalid())?)),
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+// A set of ip ranges, looked up by the longest range that
+// contains an address. Ranges are kept in a binary trie over
+// the bits of their networks, so a lookup takes at most one
+// step per bit of the address, however many ranges there are.
+#[derive(Debug, Clone, Default)]
+pub struct IpRanges {
+    v4: Trie,
+    v6: Trie,
+}
+
+impl IpRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, range: Cidr) {
+        match range.network {
+            IpAddr::V4(_) => self.v4.insert(range),
+            IpAddr::V6(_) => self.v6.insert(range),
+        }
+    }
+
+    // The most specific range that contains the address.
+    pub fn lookup(&self, ip: IpAddr) -> Option<Cidr> {
+        match ip.to_canonical() {
+            ip @ IpAddr::V4(_) => self.v4.lookup(ip),
+            ip @ IpAddr::V6(_) => self.v6.lookup(ip),
+        }
+    }
+}
+
+impl<T: Into<Cidr>> FromIterator<T> for IpRanges {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut ranges = IpRanges::new();
+        for range in iter {
+            ranges.insert(range.into());
+        }
+
+        ranges
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Trie {
+    // The root is the first node, the children of
+    // a node are indexes into the same vector.
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: [Option<usize>; 2],
+    range: Option<Cidr>,
+}
+
+impl Trie {
+    fn insert(&mut self, range: Cidr) {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node::default());
+        }
+
+        let bits = bits(range.network);
+        let mut node = 0;
+        for i in 0..range.prefix {
+            let bit = bit(bits, i);
+            node = match self.nodes[node].children[bit] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(Node::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = Some(child);
+                    child
+                }
+            };
+        }
+
+        self.nodes[node].range = Some(range);
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Option<Cidr> {
+        let bits = bits(ip);
+        let mut node = self.nodes.first()?;
+        let mut found = node.range;
+
+        for i in 0..width(ip) {
+            match node.children[bit(bits, i)] {
+                Some(child) => node = &self.nodes[child],
+                None => break,
+            }
+            found = node.range.or(found);
+        }
+
+        found
+    }
+}
+
+fn width(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+// The bits of the address, aligned to the left.
+fn bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => (u32::from(ip) as u128) << 96,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+fn bit(bits: u128, i: u8) -> usize {
+    ((bits >> (127 - i)) & 1) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let range: Cidr = "10.27.104.11/16".parse().unwrap();
+        assert_eq!(range.network(), ip("10.27.0.0"));
+        assert_eq!(range.prefix(), 16);
+        assert_eq!(range.to_string(), "10.27.0.0/16");
+
+        let range: Cidr = "2001:db8::1".parse().unwrap();
+        assert_eq!(range.prefix(), 128);
+
+        assert!("10.27.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.27.0.0/abc".parse::<Cidr>().is_err());
+        assert!("abc".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let range: Cidr = "10.27.0.0/16".parse().unwrap();
+        assert!(range.contains(ip("10.27.104.11")));
+        assert!(range.contains(ip("::ffff:10.27.104.11")));
+        assert!(!range.contains(ip("10.28.0.1")));
+        assert!(!range.contains(ip("2001:db8::1")));
+
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let ranges: IpRanges = vec![
+            "10.0.0.0/8",
+            "10.27.0.0/16",
+            "10.27.104.11",
+            "2001:db8::/32",
+        ]
+        .into_iter()
+        .map(|s| s.parse::<Cidr>().unwrap())
+        .collect();
+
+        let lookup = |s| ranges.lookup(ip(s)).map(|range| range.to_string());
+        assert_eq!(lookup("10.1.2.3"), Some("10.0.0.0/8".to_owned()));
+        assert_eq!(lookup("10.27.1.1"), Some("10.27.0.0/16".to_owned()));
+        assert_eq!(lookup("10.27.104.11"), Some("10.27.104.11/32".to_owned()));
+        assert_eq!(lookup("11.0.0.1"), None);
+        assert_eq!(lookup("2001:db8:1::1"), Some("2001:db8::/32".to_owned()));
+        assert_eq!(lookup("2001:db9::1"), None);
+
+        assert!(IpRanges::new().lookup(ip("10.0.0.1")).is_none());
+    }
+}
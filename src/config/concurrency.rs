@@ -0,0 +1,66 @@
+use std::{collections::HashSet, time::Duration};
+
+use serde::{de, Deserialize, Deserializer};
+
+// A limit on the number of requests an id can have in flight
+// at once, written as a `[[concurrency]]` table. A request holds
+// a lease from the moment it is acquired until it is released,
+// or until its ttl runs out if it never is.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Concurrency {
+    name: String,
+    limit: u32,
+    // How long a lease is held for if it's not
+    // released, in seconds. 60 by default.
+    ttl: Option<u64>,
+}
+
+impl Concurrency {
+    #[allow(dead_code)]
+    pub fn new<T: Into<String>>(name: T, limit: u32) -> Self {
+        Self {
+            name: name.into(),
+            limit,
+            ttl: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl.as_secs());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl.unwrap_or(60))
+    }
+}
+
+// The leases are stored under the names of their
+// limiters, so no two of them can have the same name.
+pub fn unique<'de, D>(deserializer: D) -> Result<Option<Vec<Concurrency>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let limiters = Option::<Vec<Concurrency>>::deserialize(deserializer)?;
+
+    let mut names = HashSet::new();
+    for limiter in limiters.iter().flatten() {
+        if !names.insert(limiter.name.as_str()) {
+            return Err(de::Error::custom(format!(
+                "duplicate concurrency name: {}",
+                limiter.name
+            )));
+        }
+    }
+
+    Ok(limiters)
+}
@@ -1,8 +1,16 @@
-use std::net::Ipv4Addr;
+use std::{net::IpAddr, time::Duration};
 
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 
-use super::{Ip, Path};
+use super::{
+    concurrency, quota, rule, AccessList, Cidr, Concurrency, Ip, KeyTemplate, Path, PathPattern,
+    Penalty, Quota, Rule, Secret, Tier, Tiers,
+};
+use crate::{
+    algorithm::{Algorithm, Rate},
+    backend::BackendKind,
+    helpers::KeyHasher,
+};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -23,6 +31,95 @@ pub struct Config {
     host: Option<String>,
 
     limits: Option<Limits>,
+
+    // The algorithm that is used for limiting,
+    // sliding window log by default.
+    algorithm: Option<Algorithm>,
+
+    // The maximum number of tokens a bucket can hold,
+    // used by the token bucket algorithm.
+    #[serde(default, deserialize_with = "positive_capacity")]
+    capacity: Option<u32>,
+
+    // Tokens added to a bucket per second,
+    // used by the token bucket algorithm.
+    #[serde(default, deserialize_with = "positive_refill_rate")]
+    refill_rate: Option<f64>,
+
+    // The maximum number of requests that can wait
+    // for their turn, used by the leaky bucket algorithm.
+    max_queue: Option<u32>,
+
+    // The backend that the limits are stored in,
+    // memory by default.
+    backend: Option<BackendKind>,
+
+    // The url of the redis server,
+    // used by the redis backend.
+    redis_url: Option<String>,
+
+    // The number of shards the ids are partitioned
+    // across, used by the sharded memory backend.
+    shards: Option<usize>,
+
+    // The number of actix workers,
+    // the number of CPUs by default.
+    workers: Option<usize>,
+
+    // How often the idle ids are dropped from memory,
+    // in seconds. Never when it's zero.
+    sweep_interval: Option<u64>,
+
+    // The maximum number of ids kept in memory, the least
+    // recently used ones are evicted to make room.
+    max_keys: Option<usize>,
+
+    // The prefix length ipv6 addresses are limited by, a
+    // single client usually owns a whole /64 network.
+    ipv6_prefix: Option<u8>,
+
+    // Whether the requests that are denied count against
+    // the limits too, an id that keeps retrying while it's
+    // limited stays limited then. True by default. Only the
+    // log counts the requests it denies itself.
+    count_rejected: Option<bool>,
+
+    // Named limits, every rule that matches a
+    // request is checked on top of the others.
+    #[serde(default, deserialize_with = "rule::unique")]
+    rules: Option<Vec<Rule>>,
+
+    // The template the key of a request is built from,
+    // the id of the request is the key by default.
+    key: Option<KeyTemplate>,
+
+    // The secret keys are hashed with into the ids they are
+    // stored under. Instances that share a backend need the
+    // same one, a random one is used with the memory backends.
+    hash_secret: Option<Secret>,
+
+    // Tiers of ids with their own limits,
+    // the global limit is used otherwise.
+    tiers: Option<Tiers>,
+
+    // Quotas over calendar periods, checked
+    // on top of the limits above.
+    #[serde(default, deserialize_with = "quota::unique")]
+    quotas: Option<Vec<Quota>>,
+
+    // Limits on the requests in flight, held by
+    // the leases of the acquire endpoint.
+    #[serde(default, deserialize_with = "concurrency::unique")]
+    concurrency: Option<Vec<Concurrency>>,
+
+    // Bans the ids that keep getting denied,
+    // no id is ever banned otherwise.
+    penalty: Option<Penalty>,
+
+    // The requests that are let through, and the ones that are
+    // turned away, without being limited. Deny goes first.
+    allow: Option<AccessList>,
+    deny: Option<AccessList>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -33,7 +130,7 @@ pub struct Limits {
 
 impl Limits {
     pub fn new(path: Option<Path>, ip: Option<Ip>) -> Self {
-        Self { path: path, ip: ip }
+        Self { path, ip }
     }
 
     fn empty() -> Self {
@@ -54,12 +151,33 @@ impl Config {
         limits: Limits,
     ) -> Self {
         Self {
-            limit: limit.unwrap_or(50 as u32),
-            ip_addr_limit: ip_addr_limit.unwrap_or(16 as u16),
-            window_time: window_time.unwrap_or(300 as u16),
-            port: Some(port.unwrap_or("8000".to_owned())),
-            host: Some(host.unwrap_or("127.0.0.1".to_owned())),
+            limit: limit.unwrap_or(50),
+            ip_addr_limit: ip_addr_limit.unwrap_or(16),
+            window_time: window_time.unwrap_or(300),
+            port: Some(port.unwrap_or_else(|| "8000".to_owned())),
+            host: Some(host.unwrap_or_else(|| "127.0.0.1".to_owned())),
             limits: Some(limits),
+            algorithm: None,
+            capacity: None,
+            refill_rate: None,
+            max_queue: None,
+            backend: None,
+            redis_url: None,
+            shards: None,
+            workers: None,
+            sweep_interval: None,
+            max_keys: None,
+            ipv6_prefix: None,
+            count_rejected: None,
+            rules: None,
+            key: None,
+            hash_secret: None,
+            tiers: None,
+            quotas: None,
+            concurrency: None,
+            penalty: None,
+            allow: None,
+            deny: None,
         }
     }
 
@@ -94,8 +212,319 @@ impl Config {
     }
 
     pub fn host_and_port(&self) -> String {
-        let host_and_port = vec![self.host.clone().unwrap(), self.port.clone().unwrap()];
-        host_and_port.join(":").to_owned()
+        let host_and_port = [self.host.clone().unwrap(), self.port.clone().unwrap()];
+        host_and_port.join(":")
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm.unwrap_or_default()
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) -> Algorithm {
+        self.algorithm = Some(algorithm);
+
+        self.algorithm()
+    }
+
+    // Defaults to the limit, so a full bucket allows
+    // as many requests as a single window does.
+    pub fn capacity(&self) -> u32 {
+        self.capacity.unwrap_or(self.limit)
+    }
+
+    pub fn set_capacity(&mut self, capacity: u32) -> u32 {
+        self.capacity = Some(capacity);
+
+        self.capacity()
+    }
+
+    // Defaults to refilling the limit once per window.
+    pub fn refill_rate(&self) -> f64 {
+        self.refill_rate
+            .unwrap_or(self.limit as f64 / self.window_time.max(1) as f64)
+    }
+
+    pub fn set_refill_rate(&mut self, refill_rate: f64) -> f64 {
+        self.refill_rate = Some(refill_rate);
+
+        self.refill_rate()
+    }
+
+    // Defaults to the limit, so a full queue drains in
+    // a single window.
+    pub fn max_queue(&self) -> u32 {
+        self.max_queue.unwrap_or(self.limit)
+    }
+
+    pub fn set_max_queue(&mut self, max_queue: u32) -> u32 {
+        self.max_queue = Some(max_queue);
+
+        self.max_queue()
+    }
+
+    pub fn backend(&self) -> BackendKind {
+        self.backend.unwrap_or_default()
+    }
+
+    pub fn set_backend(&mut self, backend: BackendKind) -> BackendKind {
+        self.backend = Some(backend);
+        self.hash_ids();
+
+        self.backend()
+    }
+
+    pub fn redis_url(&self) -> String {
+        self.redis_url
+            .clone()
+            .unwrap_or_else(|| "redis://127.0.0.1/".to_owned())
+    }
+
+    pub fn set_redis_url(&mut self, redis_url: String) -> String {
+        self.redis_url = Some(redis_url);
+
+        self.redis_url()
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.unwrap_or(16)
+    }
+
+    pub fn set_shards(&mut self, shards: usize) -> usize {
+        self.shards = Some(shards);
+
+        self.shards()
+    }
+
+    pub fn workers(&self) -> Option<usize> {
+        self.workers
+    }
+
+    pub fn set_workers(&mut self, workers: usize) -> Option<usize> {
+        self.workers = Some(workers);
+
+        self.workers()
+    }
+
+    pub fn sweep_interval(&self) -> u64 {
+        self.sweep_interval.unwrap_or(60)
+    }
+
+    pub fn set_sweep_interval(&mut self, sweep_interval: u64) -> u64 {
+        self.sweep_interval = Some(sweep_interval);
+
+        self.sweep_interval()
+    }
+
+    pub fn max_keys(&self) -> Option<usize> {
+        self.max_keys
+    }
+
+    pub fn set_max_keys(&mut self, max_keys: usize) -> Option<usize> {
+        self.max_keys = Some(max_keys);
+
+        self.max_keys()
+    }
+
+    pub fn ipv6_prefix(&self) -> u8 {
+        self.ipv6_prefix.unwrap_or(64).min(128)
+    }
+
+    pub fn set_ipv6_prefix(&mut self, ipv6_prefix: u8) -> u8 {
+        self.ipv6_prefix = Some(ipv6_prefix);
+
+        self.ipv6_prefix()
+    }
+
+    pub fn count_rejected(&self) -> bool {
+        self.count_rejected.unwrap_or(true)
+    }
+
+    pub fn set_count_rejected(&mut self, count_rejected: bool) -> bool {
+        self.count_rejected = Some(count_rejected);
+
+        self.count_rejected()
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        self.rules.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<Rule>) -> &[Rule] {
+        self.rules = Some(rules);
+
+        self.rules()
+    }
+
+    pub fn key(&self) -> Option<&KeyTemplate> {
+        self.key.as_ref()
+    }
+
+    pub fn set_key(&mut self, key: KeyTemplate) -> Option<&KeyTemplate> {
+        self.key = Some(key);
+
+        self.key()
+    }
+
+    // Hashes the keys with the secret. Without one, the redis
+    // backend hashes them with a key every instance knows, and
+    // the memory backends with a random one.
+    pub fn hasher(&self) -> KeyHasher {
+        match (&self.hash_secret, self.backend()) {
+            (Some(secret), _) => KeyHasher::new(secret.expose()),
+            (None, BackendKind::Redis) => KeyHasher::new(""),
+            (None, _) => KeyHasher::random(),
+        }
+    }
+
+    pub fn hash_secret(&self) -> Option<&Secret> {
+        self.hash_secret.as_ref()
+    }
+
+    // The listed ids are hashed again with the new secret.
+    pub fn set_hash_secret(&mut self, secret: Secret) -> Option<&Secret> {
+        self.hash_secret = Some(secret);
+        self.hash_ids();
+
+        self.hash_secret()
+    }
+
+    // The id a key is stored under in the backend.
+    pub fn id_of(&self, key: &str) -> u64 {
+        self.hasher().id_of(key)
+    }
+
+    // Hashes the ids of the allow and the deny lists,
+    // once the secret they are hashed with is known.
+    pub fn hash_ids(&mut self) {
+        let hasher = self.hasher();
+        for list in self.allow.iter_mut().chain(self.deny.iter_mut()) {
+            list.hash_ids(&hasher);
+        }
+    }
+
+    pub fn tier(&self, name: &str) -> Option<&Tier> {
+        self.tiers.as_ref()?.get(name)
+    }
+
+    // The name of the tier the key of an id is listed in.
+    pub fn tier_of(&self, key: &str) -> Option<&str> {
+        self.tiers.as_ref()?.tier_of(key)
+    }
+
+    pub fn tiers(&self) -> impl Iterator<Item = (&str, &Tier)> {
+        self.tiers.iter().flat_map(Tiers::iter)
+    }
+
+    pub fn set_tiers(&mut self, tiers: Tiers) {
+        self.tiers = Some(tiers);
+    }
+
+    pub fn quotas(&self) -> &[Quota] {
+        self.quotas.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_quotas(&mut self, quotas: Vec<Quota>) -> &[Quota] {
+        self.quotas = Some(quotas);
+
+        self.quotas()
+    }
+
+    pub fn concurrency(&self, name: &str) -> Option<&Concurrency> {
+        self.concurrency
+            .iter()
+            .flatten()
+            .find(|limiter| limiter.name() == name)
+    }
+
+    pub fn concurrency_limiters(&self) -> &[Concurrency] {
+        self.concurrency.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_concurrency(&mut self, limiters: Vec<Concurrency>) -> &[Concurrency] {
+        self.concurrency = Some(limiters);
+
+        self.concurrency_limiters()
+    }
+
+    pub fn penalty(&self) -> Option<&Penalty> {
+        self.penalty.as_ref()
+    }
+
+    pub fn set_penalty(&mut self, penalty: Penalty) -> Option<&Penalty> {
+        self.penalty = Some(penalty);
+
+        self.penalty()
+    }
+
+    pub fn allow(&self) -> Option<&AccessList> {
+        self.allow.as_ref()
+    }
+
+    pub fn set_allow(&mut self, allow: AccessList) -> Option<&AccessList> {
+        self.allow = Some(allow);
+        self.hash_ids();
+
+        self.allow()
+    }
+
+    pub fn deny(&self) -> Option<&AccessList> {
+        self.deny.as_ref()
+    }
+
+    pub fn set_deny(&mut self, deny: AccessList) -> Option<&AccessList> {
+        self.deny = Some(deny);
+        self.hash_ids();
+
+        self.deny()
+    }
+
+    // The network the address is limited as. Ipv6 addresses
+    // are cut to the configured prefix, ipv4 addresses, also
+    // the ones mapped to ipv6, are limited on their own.
+    pub fn network(&self, ip: IpAddr) -> IpAddr {
+        match ip.to_canonical() {
+            ip @ IpAddr::V6(_) => Cidr::new(ip, self.ipv6_prefix()).unwrap().network(),
+            ip => ip,
+        }
+    }
+
+    // The rate every id is limited with. A token bucket
+    // holds `capacity` tokens and refills all of them in
+    // `capacity / refill_rate` seconds.
+    pub fn rate(&self) -> Rate {
+        self.rate_of(None)
+    }
+
+    // The rate of the ids in the tier. The limit and window
+    // time of the tier replace the global ones, and so do the
+    // capacity and refill rate they default to.
+    pub fn rate_of(&self, tier: Option<&Tier>) -> Rate {
+        let (limit, window_time) = match tier {
+            Some(tier) => (tier.limit(), tier.window_time().unwrap_or(self.window_time)),
+            None => (self.limit, self.window_time),
+        };
+
+        match self.algorithm() {
+            Algorithm::TokenBucket => {
+                let (capacity, refill_rate) = match tier {
+                    Some(_) => (limit, limit as f64 / window_time.max(1) as f64),
+                    None => (self.capacity(), self.refill_rate()),
+                };
+                // an empty bucket, with a limit of zero, never
+                // refills, the window time is as good as any period.
+                let period = Duration::try_from_secs_f64(capacity as f64 / refill_rate)
+                    .unwrap_or_else(|_| Duration::from_secs(window_time as u64));
+                Rate::new(Algorithm::TokenBucket, capacity, period)
+            }
+            algorithm => {
+                let max_queue = match tier {
+                    Some(_) => limit,
+                    None => self.max_queue(),
+                };
+                Rate::new(algorithm, limit, Duration::from_secs(window_time as u64))
+                    .with_max_queue(max_queue)
+            }
+        }
     }
 
     pub(crate) fn limits_is_some(&self) -> bool {
@@ -126,6 +555,21 @@ impl Config {
         None
     }
 
+    // The most specific limited path pattern the request
+    // matches, and the path its requests are limited as. That's
+    // the pattern when its paths share the limit, and the path
+    // itself otherwise.
+    pub fn limited_path(&self, path: &str, method: Option<&str>) -> Option<(&PathPattern, String)> {
+        let path_limit = self.limits.as_ref()?.path.as_ref()?;
+        let pattern = path_limit.matching(path, method)?;
+
+        if path_limit.shared() {
+            Some((pattern, pattern.to_string()))
+        } else {
+            Some((pattern, path.to_owned()))
+        }
+    }
+
     pub fn path_limit(&self) -> Option<u32> {
         if self.limit_path_is_some() {
             return self.limits.as_ref().unwrap().path.as_ref().unwrap().limit();
@@ -149,7 +593,7 @@ impl Config {
         None
     }
 
-    pub fn limited_ip_addresses(&self) -> Option<Vec<Ipv4Addr>> {
+    pub fn limited_ip_addresses(&self) -> Option<Vec<IpAddr>> {
         if self.limit_ip_is_some() {
             return self
                 .limits
@@ -164,6 +608,21 @@ impl Config {
         None
     }
 
+    // The address the requests from the ip address are limited
+    // as, if it is in one of the limited ranges. That's the
+    // network of the range when its addresses share the limit,
+    // and the network of the address otherwise.
+    pub fn limited_ip_address(&self, ip: IpAddr) -> Option<IpAddr> {
+        let ip_limit = self.limits.as_ref()?.ip.as_ref()?;
+        let range = ip_limit.range(ip)?;
+
+        if ip_limit.shared() {
+            Some(range.network())
+        } else {
+            Some(self.network(ip))
+        }
+    }
+
     pub fn ip_addresses_limit(&self) -> Option<u32> {
         if self.limit_ip_is_some() {
             return self.limits.as_ref().unwrap().ip.as_ref().unwrap().limit();
@@ -188,15 +647,61 @@ impl Config {
     }
 }
 
+// A bucket that holds no tokens denies every request.
+fn positive_capacity<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<u32>::deserialize(deserializer)? {
+        Some(0) => Err(de::Error::custom("capacity must be at least 1")),
+        capacity => Ok(capacity),
+    }
+}
+
+// A bucket that is never refilled has no period.
+fn positive_refill_rate<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(rate) if !(rate.is_finite() && rate > 0.0) => Err(de::Error::custom(format!(
+            "refill_rate must be a positive number: {}",
+            rate
+        ))),
+        refill_rate => Ok(refill_rate),
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
-            limit: 50 as u32,
-            ip_addr_limit: 5 as u16,
-            window_time: 300 as u16,
+            limit: 50,
+            ip_addr_limit: 5,
+            window_time: 300,
             host: Some("127.0.0.1".to_owned()),
             port: Some("8000".to_owned()),
             limits: Some(Limits::empty()),
+            algorithm: None,
+            capacity: None,
+            refill_rate: None,
+            max_queue: None,
+            backend: None,
+            redis_url: None,
+            shards: None,
+            workers: None,
+            sweep_interval: None,
+            max_keys: None,
+            ipv6_prefix: None,
+            count_rejected: None,
+            rules: None,
+            key: None,
+            hash_secret: None,
+            tiers: None,
+            quotas: None,
+            concurrency: None,
+            penalty: None,
+            allow: None,
+            deny: None,
         }
     }
 }
@@ -221,4 +726,487 @@ mod tests {
         assert!(config.limit() == 100);
         assert!(config.ip_addr_limit() == 25);
     }
+
+    #[test]
+    fn test_token_bucket_defaults() {
+        let mut config = Config::default();
+
+        assert_eq!(config.algorithm(), Algorithm::SlidingWindowLog);
+        assert_eq!(config.capacity(), 50);
+        assert_eq!(config.refill_rate(), 50.0 / 300.0);
+
+        config.set_algorithm(Algorithm::TokenBucket);
+        config.set_capacity(10);
+        config.set_refill_rate(2.5);
+
+        assert_eq!(config.algorithm(), Algorithm::TokenBucket);
+        assert_eq!(config.capacity(), 10);
+        assert_eq!(config.refill_rate(), 2.5);
+        assert_eq!(
+            config.rate(),
+            Rate::new(Algorithm::TokenBucket, 10, Duration::from_secs(4))
+        );
+
+        config.set_algorithm(Algorithm::SlidingWindowCounter);
+        assert_eq!(
+            config.rate(),
+            Rate::new(
+                Algorithm::SlidingWindowCounter,
+                50,
+                Duration::from_secs(300)
+            )
+        );
+    }
+
+    #[test]
+    fn test_algorithm_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+            algorithm = "token_bucket"
+            capacity = 20
+            refill_rate = 0.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.algorithm(), Algorithm::TokenBucket);
+        assert_eq!(config.capacity(), 20);
+        assert_eq!(config.refill_rate(), 0.5);
+        assert_eq!(config.backend(), BackendKind::Memory);
+    }
+
+    #[test]
+    fn test_backend_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+            backend = "redis"
+            redis_url = "redis://10.27.104.11:6379/"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.backend(), BackendKind::Redis);
+        assert_eq!(config.redis_url(), "redis://10.27.104.11:6379/");
+        assert_eq!(config.shards(), 16);
+        assert_eq!(config.workers(), None);
+        assert_eq!(config.sweep_interval(), 60);
+        assert_eq!(config.max_keys(), None);
+        assert_eq!(config.ipv6_prefix(), 64);
+        assert!(config.count_rejected());
+
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+            backend = "sharded_memory"
+            shards = 64
+            workers = 4
+            sweep_interval = 10
+            max_keys = 1000000
+            count_rejected = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.backend(), BackendKind::ShardedMemory);
+        assert_eq!(config.shards(), 64);
+        assert_eq!(config.workers(), Some(4));
+        assert_eq!(config.sweep_interval(), 10);
+        assert_eq!(config.max_keys(), Some(1000000));
+        assert!(!config.count_rejected());
+    }
+
+    #[test]
+    fn test_ipv6_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+            ipv6_prefix = 48
+
+            [limits.ip]
+            ip_addresses = ["10.27.104.11", "2001:db8:85a3::8a2e:370:7334"]
+            limit = 100
+            "#,
+        )
+        .unwrap();
+
+        let ip_addresses = config.limited_ip_addresses().unwrap();
+        assert_eq!(ip_addresses[0], "10.27.104.11".parse::<IpAddr>().unwrap());
+        assert_eq!(
+            config.network(ip_addresses[1]),
+            "2001:db8:85a3::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            config.network("::ffff:10.27.104.11".parse().unwrap()),
+            ip_addresses[0]
+        );
+
+        let mut config = Config::default();
+        let ip = "2001:db8:85a3:1:2:8a2e:370:7334".parse().unwrap();
+        assert_eq!(
+            config.network(ip),
+            "2001:db8:85a3:1::".parse::<IpAddr>().unwrap()
+        );
+        config.set_ipv6_prefix(128);
+        assert_eq!(config.network(ip), ip);
+        config.set_ipv6_prefix(0);
+        assert_eq!(config.network(ip), "::".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_ip_ranges_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [limits.ip]
+            ip_addresses = ["10.27.104.11"]
+            ip_ranges = ["10.27.0.0/16", "2001:db8::/32"]
+            limit = 100
+            "#,
+        )
+        .unwrap();
+
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            config.limited_ip_address(ip("10.27.1.1")),
+            Some(ip("10.27.1.1"))
+        );
+        assert_eq!(
+            config.limited_ip_address(ip("2001:db8:1:2:3::1")),
+            Some(ip("2001:db8:1:2::"))
+        );
+        assert_eq!(config.limited_ip_address(ip("10.28.1.1")), None);
+
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [limits.ip]
+            ip_ranges = ["10.27.0.0/16"]
+            shared = true
+            limit = 100
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.limited_ip_address(ip("10.27.1.1")),
+            Some(ip("10.27.0.0"))
+        );
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [limits.ip]
+            ip_ranges = ["10.27.0.0/33"]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_path_patterns_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [limits.path]
+            paths = ["/users/*/orders", "regex:^/users/\\d+$"]
+            methods = ["POST"]
+            shared = true
+            limit = 20
+            "#,
+        )
+        .unwrap();
+
+        let limited_path = |path, method| {
+            config
+                .limited_path(path, method)
+                .map(|(pattern, path)| (pattern.to_string(), path))
+        };
+        assert_eq!(
+            limited_path("/users/1/orders", Some("POST")),
+            Some(("/users/*/orders".to_owned(), "/users/*/orders".to_owned()))
+        );
+        assert_eq!(
+            limited_path("/users/1", Some("post")),
+            Some((
+                r"regex:^/users/\d+$".to_owned(),
+                r"regex:^/users/\d+$".to_owned()
+            ))
+        );
+        assert_eq!(limited_path("/users/1/orders", Some("GET")), None);
+        assert_eq!(limited_path("/users/abc", Some("POST")), None);
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [limits.path]
+            paths = ["regex:^/users/("]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rules_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [[rules]]
+            name = "login"
+            paths = ["/login"]
+            methods = ["POST"]
+            limit = 5
+            window_time = 60
+
+            [[rules]]
+            name = "internal"
+            ip_addresses = ["10.0.0.0/8"]
+            algorithm = "token_bucket"
+            limit = 1000
+            "#,
+        )
+        .unwrap();
+
+        let rules = config.rules();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name(), "login");
+        assert_eq!(
+            rules[0].rate(config.algorithm(), config.window_time()),
+            Rate::new(Algorithm::SlidingWindowLog, 5, Duration::from_secs(60))
+        );
+        assert_eq!(
+            rules[1].rate(config.algorithm(), config.window_time()),
+            Rate::new(Algorithm::TokenBucket, 1000, Duration::from_secs(300))
+        );
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [[rules]]
+            name = "login"
+            limit = 5
+
+            [[rules]]
+            name = "login"
+            limit = 10
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_tiers_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [tiers.free]
+            limit = 100
+
+            [tiers.pro]
+            limit = 1000
+            window_time = 60
+            ids = ["acme", "8293489298213"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.tier_of("acme"), Some("pro"));
+        assert_eq!(config.tier_of("globex"), None);
+        assert_eq!(
+            config.rate_of(config.tier("free")),
+            Rate::new(Algorithm::SlidingWindowLog, 100, Duration::from_secs(300))
+        );
+        assert_eq!(
+            config.rate_of(config.tier("pro")),
+            Rate::new(Algorithm::SlidingWindowLog, 1000, Duration::from_secs(60))
+        );
+        assert_eq!(config.rate_of(None), config.rate());
+    }
+
+    #[test]
+    fn test_quotas_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [[quotas]]
+            name = "daily"
+            period = "day"
+            limit = 10000
+
+            [[quotas]]
+            name = "monthly"
+            period = "month"
+            limit = 100000
+            utc_offset = "-05:00"
+            "#,
+        )
+        .unwrap();
+
+        let quotas = config.quotas();
+        assert_eq!(quotas.len(), 2);
+        assert_eq!(quotas[1].name(), "monthly");
+        assert_eq!(quotas[1].period(), crate::config::Period::Month);
+        assert_eq!(quotas[1].limit(), 100000);
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [[quotas]]
+            name = "daily"
+            period = "fortnight"
+            limit = 10000
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_concurrency_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [[concurrency]]
+            name = "reports"
+            limit = 3
+            ttl = 600
+
+            [[concurrency]]
+            name = "uploads"
+            limit = 10
+            "#,
+        )
+        .unwrap();
+
+        let reports = config.concurrency("reports").unwrap();
+        assert_eq!(reports.limit(), 3);
+        assert_eq!(reports.ttl(), Duration::from_secs(600));
+        assert_eq!(
+            config.concurrency("uploads").unwrap().ttl(),
+            Duration::from_secs(60)
+        );
+        assert!(config.concurrency("exports").is_none());
+
+        assert!(toml::from_str::<Config>(
+            r#"
+            limit = 300
+            ip_addr_limit = 5
+            window_time = 300
+
+            [[concurrency]]
+            name = "reports"
+            limit = 3
+
+            [[concurrency]]
+            name = "reports"
+            limit = 5
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_validation() {
+        let config = |table: &str| {
+            toml::from_str::<Config>(&format!(
+                "limit = 300\nip_addr_limit = 5\nwindow_time = 300\nalgorithm = \"token_bucket\"\n{}",
+                table
+            ))
+        };
+
+        assert!(config("refill_rate = 0.0").is_err());
+        assert!(config("refill_rate = -1.5").is_err());
+        assert!(config("refill_rate = nan").is_err());
+        assert!(config("capacity = 0").is_err());
+        assert!(config("capacity = 10\nrefill_rate = 2.5").is_ok());
+
+        // a limit of zero makes an empty bucket, which must not
+        // overflow the expiry of its state.
+        let mut config = Config::default();
+        config.set_algorithm(Algorithm::TokenBucket);
+        config.set_limit(0);
+        let rate = config.rate();
+        assert_eq!(rate.period, Duration::from_secs(300));
+        assert!(rate.expiry() > rate.period);
+        assert_eq!(
+            Rate::new(Algorithm::TokenBucket, 1, Duration::MAX).expiry(),
+            Duration::MAX
+        );
+    }
+
+    #[test]
+    fn test_hash_secret_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 10
+            ip_addr_limit = 5
+            window_time = 60
+            backend = "redis"
+            hash_secret = "s3cret"
+
+            [allow]
+            ids = ["acme"]
+            "#,
+        )
+        .unwrap();
+
+        // the secret is never printed along with the config.
+        assert!(!format!("{:?}", config).contains("s3cret"));
+        assert_eq!(config.hasher(), KeyHasher::new("s3cret"));
+
+        let mut config = config;
+        let id = config.id_of("acme");
+        assert!(!config.allow().unwrap().contains(id, None, None));
+        config.hash_ids();
+        assert!(config.allow().unwrap().contains(id, None, None));
+
+        // the listed ids follow the secret.
+        config.set_hash_secret(Secret::new("other"));
+        assert!(!config.allow().unwrap().contains(id, None, None));
+        assert!(config
+            .allow()
+            .unwrap()
+            .contains(config.id_of("acme"), None, None));
+    }
 }
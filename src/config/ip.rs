@@ -1,32 +1,104 @@
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use serde::Deserialize;
 
+use super::{Cidr, IpRanges};
+
 #[derive(Debug, Clone, Deserialize)]
+#[serde(from = "IpTable")]
 pub struct Ip {
-    ip_addresses: Option<Vec<Ipv4Addr>>,
+    ip_addresses: Option<Vec<IpAddr>>,
+    ip_ranges: Option<Vec<Cidr>>,
+    // Whether the addresses in a range share a single
+    // limit, or every address gets a limit of its own.
+    shared: Option<bool>,
     limit: Option<u32>,
     window_time: Option<u16>,
+    // The addresses and the ranges, built once when
+    // the config is loaded.
+    ranges: IpRanges,
+}
+
+// The [limits.ip] table as it is written in the config.
+#[derive(Deserialize)]
+struct IpTable {
+    ip_addresses: Option<Vec<IpAddr>>,
+    ip_ranges: Option<Vec<Cidr>>,
+    shared: Option<bool>,
+    limit: Option<u32>,
+    window_time: Option<u16>,
+}
+
+impl From<IpTable> for Ip {
+    fn from(table: IpTable) -> Self {
+        let addresses = table
+            .ip_addresses
+            .iter()
+            .flatten()
+            .map(|ip| Cidr::from(*ip));
+        let ranges = addresses
+            .chain(table.ip_ranges.iter().flatten().copied())
+            .collect();
+
+        Self {
+            ip_addresses: table.ip_addresses,
+            ip_ranges: table.ip_ranges,
+            shared: table.shared,
+            limit: table.limit,
+            window_time: table.window_time,
+            ranges,
+        }
+    }
 }
 
 impl Ip {
     #[allow(dead_code)]
     pub fn new<I, T>(ip_addrs: I, limit: u32, window_time: u16) -> Self
     where
-        T: Into<Ipv4Addr>,
+        T: Into<IpAddr>,
         I: IntoIterator<Item = T>,
     {
-        Self {
+        Self::from(IpTable {
             ip_addresses: Some(ip_addrs.into_iter().map(Into::into).collect()),
+            ip_ranges: None,
+            shared: None,
             limit: Some(limit),
             window_time: Some(window_time),
-        }
+        })
     }
 
-    pub fn ip_addresses(&self) -> Option<Vec<Ipv4Addr>> {
+    #[allow(dead_code)]
+    pub fn with_ip_ranges<I>(self, ip_ranges: I, shared: bool) -> Self
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        Self::from(IpTable {
+            ip_addresses: self.ip_addresses,
+            ip_ranges: Some(ip_ranges.into_iter().collect()),
+            shared: Some(shared),
+            limit: self.limit,
+            window_time: self.window_time,
+        })
+    }
+
+    pub fn ip_addresses(&self) -> Option<Vec<IpAddr>> {
         self.ip_addresses.clone()
     }
 
+    #[allow(dead_code)]
+    pub fn ip_ranges(&self) -> Option<Vec<Cidr>> {
+        self.ip_ranges.clone()
+    }
+
+    // The range of the limited addresses the ip address is in.
+    pub fn range(&self, ip: IpAddr) -> Option<Cidr> {
+        self.ranges.lookup(ip)
+    }
+
+    pub fn shared(&self) -> bool {
+        self.shared.unwrap_or(false)
+    }
+
     pub fn window_time(&self) -> Option<u16> {
         self.window_time
     }
@@ -38,6 +110,8 @@ impl Ip {
 
 #[cfg(test)]
 mod tests {
+    use std::net::Ipv4Addr;
+
     use super::*;
 
     #[test]
@@ -0,0 +1,126 @@
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use serde::Deserialize;
+
+// The template the key of a request is built from, written
+// as `{tenant}:{user}:{path}`. Every `{field}` is replaced
+// with the field of the same name in the request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+impl KeyTemplate {
+    // Builds the key with the given fields, fails if
+    // the request doesn't have one of them.
+    pub fn render<F>(&self, field: F) -> Result<String, String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut key = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => key.push_str(literal),
+                Part::Field(name) => match field(name) {
+                    Some(value) => key.push_str(&value),
+                    None => return Err(format!("missing field: {}", name)),
+                },
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+impl FromStr for KeyTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid key template: {}", s);
+
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            match rest.find(['{', '}']) {
+                Some(start) if rest[start..].starts_with('{') => {
+                    if start > 0 {
+                        parts.push(Part::Literal(rest[..start].to_owned()));
+                    }
+
+                    let end = rest[start..].find('}').ok_or_else(invalid)? + start;
+                    let name = &rest[start + 1..end];
+                    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        return Err(invalid());
+                    }
+
+                    parts.push(Part::Field(name.to_owned()));
+                    rest = &rest[end + 1..];
+                }
+                Some(_) => return Err(invalid()),
+                None => {
+                    parts.push(Part::Literal(rest.to_owned()));
+                    rest = "";
+                }
+            }
+        }
+
+        Ok(Self {
+            source: s.to_owned(),
+            parts,
+        })
+    }
+}
+
+impl TryFrom<String> for KeyTemplate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for KeyTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template: KeyTemplate = "{tenant}:{user}:{path}".parse().unwrap();
+        let field = |name: &str| match name {
+            "tenant" => Some("acme".to_owned()),
+            "user" => Some("42".to_owned()),
+            "path" => Some("/orders".to_owned()),
+            _ => None,
+        };
+        assert_eq!(template.render(field), Ok("acme:42:/orders".to_owned()));
+
+        let template: KeyTemplate = "api:{api_key}".parse().unwrap();
+        assert_eq!(
+            template.render(field),
+            Err("missing field: api_key".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!("static".parse::<KeyTemplate>().is_ok());
+        assert!("{tenant".parse::<KeyTemplate>().is_err());
+        assert!("tenant}".parse::<KeyTemplate>().is_err());
+        assert!("{}".parse::<KeyTemplate>().is_err());
+        assert!("{ten ant}".parse::<KeyTemplate>().is_err());
+    }
+}
@@ -1,8 +1,29 @@
+mod access;
+mod cidr;
+mod concurrency;
+#[allow(clippy::module_inception)]
 mod config;
 mod ip;
+mod key;
 mod parser;
 mod path;
+mod pattern;
+mod penalty;
+mod quota;
+mod rule;
+mod secret;
+mod tier;
 
+pub use access::AccessList;
+pub use cidr::{Cidr, IpRanges};
+pub use concurrency::Concurrency;
 pub use config::{Config, Limits};
 pub use ip::Ip;
+pub use key::KeyTemplate;
 pub use path::Path;
+pub use pattern::PathPattern;
+pub use penalty::Penalty;
+pub use quota::{Period, Quota};
+pub use rule::Rule;
+pub use secret::Secret;
+pub use tier::{Tier, Tiers};
@@ -7,7 +7,8 @@ impl Config {
         let mut file = File::open(path).unwrap();
         let mut contents = String::new();
         file.read_to_string(&mut contents).unwrap();
-        let config: Config = toml::from_str(&contents).unwrap();
+        let mut config: Config = toml::from_str(&contents).unwrap();
+        config.hash_ids();
         println!("{:#?}", config);
         config
     }
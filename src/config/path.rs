@@ -1,8 +1,18 @@
 use serde::Deserialize;
 
+use super::PathPattern;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Path {
-    paths: Option<Vec<String>>,
+    paths: Option<Vec<PathPattern>>,
+    // The methods the limit applies to, every
+    // method if there are none.
+    methods: Option<Vec<String>>,
+    // Whether the paths that match the same pattern share a
+    // single limit, or every path gets a limit of its own. Shared
+    // by default, otherwise a client could get around the limit
+    // of a glob by changing its wildcard segments.
+    shared: Option<bool>,
     limit: Option<u32>,
     window_time: Option<u16>,
 }
@@ -11,18 +21,55 @@ impl Path {
     #[allow(dead_code)]
     pub fn new<I, T>(endpoints: I, limit: u32, window_time: u16) -> Self
     where
-        T: Into<String>,
+        T: Into<PathPattern>,
         I: IntoIterator<Item = T>,
     {
         Self {
             paths: Some(endpoints.into_iter().map(Into::into).collect()),
+            methods: None,
+            shared: None,
             limit: Some(limit),
             window_time: Some(window_time),
         }
     }
 
+    #[allow(dead_code)]
+    pub fn with_methods<I, T>(mut self, methods: I) -> Self
+    where
+        T: Into<String>,
+        I: IntoIterator<Item = T>,
+    {
+        self.methods = Some(methods.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_shared(mut self, shared: bool) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
     pub fn paths(&self) -> Option<Vec<String>> {
-        self.paths.clone()
+        self.paths
+            .as_ref()
+            .map(|paths| paths.iter().map(ToString::to_string).collect())
+    }
+
+    // The most specific pattern that matches the path,
+    // if the method is one of the limited methods.
+    pub fn matching(&self, path: &str, method: Option<&str>) -> Option<&PathPattern> {
+        if let Some(ref methods) = self.methods {
+            let method = method?;
+            if !methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+                return None;
+            }
+        }
+
+        PathPattern::most_specific(self.paths.iter().flatten(), path)
+    }
+
+    pub fn shared(&self) -> bool {
+        self.shared.unwrap_or(true)
     }
 
     pub fn window_time(&self) -> Option<u16> {
@@ -42,10 +89,28 @@ mod tests {
     pub fn test_new() {
         let path = Path::new(vec!["test", "1234", "214141"], 300, 400);
 
-        assert_eq!(path.paths.clone().unwrap()[0], "test".to_owned());
-        assert_eq!(path.paths.clone().unwrap()[1], "1234".to_owned());
-        assert_eq!(path.paths.clone().unwrap()[2], "214141".to_owned());
+        assert_eq!(path.paths.clone().unwrap()[0].as_str(), "test");
+        assert_eq!(path.paths.clone().unwrap()[1].as_str(), "1234");
+        assert_eq!(path.paths.clone().unwrap()[2].as_str(), "214141");
         assert_eq!(path.limit, Some(300));
         assert_eq!(path.window_time, Some(400));
     }
+
+    #[test]
+    pub fn test_matching() {
+        let path = Path::new(vec!["/users/*/orders", "/users/1/orders"], 300, 400)
+            .with_methods(vec!["POST"]);
+
+        let matching = |p, method| path.matching(p, method).map(PathPattern::as_str);
+        assert_eq!(
+            matching("/users/1/orders", Some("post")),
+            Some("/users/1/orders")
+        );
+        assert_eq!(
+            matching("/users/2/orders", Some("POST")),
+            Some("/users/*/orders")
+        );
+        assert_eq!(matching("/users/2/orders", Some("GET")), None);
+        assert_eq!(matching("/users/2/orders", None), None);
+    }
 }
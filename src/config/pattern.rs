@@ -0,0 +1,184 @@
+use std::{cmp::Ordering, convert::TryFrom, fmt, str::FromStr};
+
+use regex::Regex;
+use serde::Deserialize;
+
+// A pattern the path of a request is matched against. Patterns
+// are written as
+//
+// `/users/orders`          the exact path
+// `/users/*/orders`        a glob, `*` matches a single segment,
+//                          `**` any number of them
+// `regex:^/users/\d+$`     a regular expression
+//
+// and compiled once, when the config is loaded.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PathPattern {
+    source: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Exact,
+    Glob(Regex),
+    Regex(Regex),
+}
+
+impl PathPattern {
+    pub fn is_match(&self, path: &str) -> bool {
+        match self.kind {
+            Kind::Exact => self.source == path,
+            Kind::Glob(ref regex) | Kind::Regex(ref regex) => regex.is_match(path),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    // Exact paths are the most specific patterns, then globs
+    // with more literal characters, then regular expressions.
+    fn specificity(&self) -> (u8, usize) {
+        match self.kind {
+            Kind::Exact => (2, self.source.len()),
+            Kind::Glob(_) => (1, self.source.chars().filter(|c| *c != '*').count()),
+            Kind::Regex(_) => (0, 0),
+        }
+    }
+
+    // The most specific of the patterns that match the path.
+    pub fn most_specific<'a, I>(patterns: I, path: &str) -> Option<&'a PathPattern>
+    where
+        I: IntoIterator<Item = &'a PathPattern>,
+    {
+        patterns
+            .into_iter()
+            .filter(|pattern| pattern.is_match(path))
+            .max_by(|a, b| match a.specificity().cmp(&b.specificity()) {
+                // the first one wins between the equally specific ones.
+                Ordering::Equal => Ordering::Greater,
+                ordering => ordering,
+            })
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = if let Some(regex) = s.strip_prefix("regex:") {
+            Kind::Regex(Regex::new(regex).map_err(|why| why.to_string())?)
+        } else if s.contains('*') {
+            Kind::Glob(glob(s).map_err(|why| why.to_string())?)
+        } else {
+            Kind::Exact
+        };
+
+        Ok(Self {
+            source: s.to_owned(),
+            kind,
+        })
+    }
+}
+
+impl TryFrom<String> for PathPattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<&str> for PathPattern {
+    // Panics if the pattern is an invalid regular expression.
+    fn from(s: &str) -> Self {
+        s.parse().unwrap()
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+// Compiles a glob to a regular expression that
+// matches the whole path.
+fn glob(s: &str) -> Result<Regex, regex::Error> {
+    let mut regex = String::from("^");
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact() {
+        let pattern = PathPattern::from("/users/orders");
+        assert!(pattern.is_match("/users/orders"));
+        assert!(!pattern.is_match("/users/orders/1"));
+    }
+
+    #[test]
+    fn test_glob() {
+        let pattern = PathPattern::from("/users/*/orders");
+        assert!(pattern.is_match("/users/123/orders"));
+        assert!(!pattern.is_match("/users/123/456/orders"));
+        assert!(!pattern.is_match("/users/123/orders/1"));
+
+        let pattern = PathPattern::from("/static/**");
+        assert!(pattern.is_match("/static/css/main.css"));
+        assert!(!pattern.is_match("/users/static/main.css"));
+
+        // everything else is taken literally.
+        let pattern = PathPattern::from("/search.json?q=*");
+        assert!(pattern.is_match("/search.json?q=dur"));
+        assert!(!pattern.is_match("/searchxjson?q=dur"));
+    }
+
+    #[test]
+    fn test_regex() {
+        let pattern = PathPattern::from(r"regex:^/users/\d+$");
+        assert!(pattern.is_match("/users/123"));
+        assert!(!pattern.is_match("/users/abc"));
+
+        assert!("regex:^/users/(".parse::<PathPattern>().is_err());
+    }
+
+    #[test]
+    fn test_most_specific() {
+        let patterns: Vec<PathPattern> = vec![
+            r"regex:^/users/.*",
+            "/users/**",
+            "/users/*/orders",
+            "/users/1/orders",
+        ]
+        .into_iter()
+        .map(PathPattern::from)
+        .collect();
+
+        let most_specific =
+            |path| PathPattern::most_specific(&patterns, path).map(PathPattern::as_str);
+        assert_eq!(most_specific("/users/1/orders"), Some("/users/1/orders"));
+        assert_eq!(most_specific("/users/2/orders"), Some("/users/*/orders"));
+        assert_eq!(most_specific("/users/2/payments"), Some("/users/**"));
+        assert_eq!(most_specific("/orders"), None);
+    }
+}
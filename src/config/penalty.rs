@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+// Bans the ids that are denied too many times in a short
+// while, written as a `[penalty]` table. Banned ids are denied
+// before any of their limits are checked, and every repeated
+// offence doubles the ban.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Penalty {
+    // The number of denied requests inside
+    // the window that gets an id banned.
+    denials: u32,
+    // Window time in seconds.
+    window_time: u64,
+    // How long the first ban lasts, in seconds.
+    ban_time: u64,
+    // The longest a ban can get, in seconds. An id that isn't
+    // banned again for this long after a ban starts over from
+    // the first ban. A day by default.
+    max_ban_time: Option<u64>,
+}
+
+impl Penalty {
+    #[allow(dead_code)]
+    pub fn new(denials: u32, window_time: Duration, ban_time: Duration) -> Self {
+        Self {
+            denials,
+            window_time: window_time.as_secs(),
+            ban_time: ban_time.as_secs(),
+            max_ban_time: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_max_ban_time(mut self, max_ban_time: Duration) -> Self {
+        self.max_ban_time = Some(max_ban_time.as_secs());
+        self
+    }
+
+    pub fn denials(&self) -> u32 {
+        self.denials
+    }
+
+    pub fn window_time(&self) -> Duration {
+        Duration::from_secs(self.window_time.max(1))
+    }
+
+    pub fn max_ban_time(&self) -> Duration {
+        Duration::from_secs(self.max_ban_time.unwrap_or(24 * 60 * 60))
+    }
+
+    // How long the ban for the given offence lasts,
+    // counting the offences from one.
+    pub fn ban_time(&self, offences: u32) -> Duration {
+        let doublings = offences.saturating_sub(1).min(63);
+        let ban_time = self.ban_time.saturating_mul(1 << doublings);
+
+        Duration::from_secs(ban_time).min(self.max_ban_time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ban_time() {
+        let penalty = Penalty::new(50, Duration::from_secs(60), Duration::from_secs(900))
+            .with_max_ban_time(Duration::from_secs(3600));
+
+        assert_eq!(penalty.ban_time(1), Duration::from_secs(900));
+        assert_eq!(penalty.ban_time(2), Duration::from_secs(1800));
+        assert_eq!(penalty.ban_time(3), Duration::from_secs(3600));
+        assert_eq!(penalty.ban_time(100), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::HashSet, convert::TryFrom, fmt, str::FromStr, time::Duration};
+
+use serde::{de, Deserialize, Deserializer};
+
+const DAY: i64 = 24 * 60 * 60;
+
+// A limit on the number of requests an id can send in a
+// calendar day, week or month, written as a `[[quotas]]`
+// table. Quotas reset on the boundaries of their periods,
+// in UTC unless they have an offset of their own.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Quota {
+    name: String,
+    period: Period,
+    limit: u64,
+    utc_offset: Option<UtcOffset>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    // Weeks start on mondays.
+    Week,
+    Month,
+}
+
+// The offset of the timezone the periods are aligned
+// to, written as `+HH:MM` or `-HH:MM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub struct UtcOffset {
+    seconds: i64,
+}
+
+impl Quota {
+    #[allow(dead_code)]
+    pub fn new<T: Into<String>>(name: T, period: Period, limit: u64) -> Self {
+        Self {
+            name: name.into(),
+            period,
+            limit,
+            utc_offset: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_utc_offset(mut self, utc_offset: UtcOffset) -> Self {
+        self.utc_offset = Some(utc_offset);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn period(&self) -> Period {
+        self.period
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    // The start and the end of the period the timestamp is in.
+    pub fn bounds(&self, timestamp: Duration) -> (Duration, Duration) {
+        let offset = self.utc_offset.unwrap_or_default().seconds;
+        let days = (timestamp.as_secs() as i64 + offset).div_euclid(DAY);
+
+        let (start, end) = match self.period {
+            Period::Day => (days, days + 1),
+            Period::Week => {
+                // the first day of the epoch was a thursday.
+                let start = days - (days + 3).rem_euclid(7);
+                (start, start + 7)
+            }
+            Period::Month => {
+                let (year, month) = civil_from_days(days);
+                let (next_year, next_month) = match month {
+                    12 => (year + 1, 1),
+                    _ => (year, month + 1),
+                };
+                (
+                    days_from_civil(year, month),
+                    days_from_civil(next_year, next_month),
+                )
+            }
+        };
+        let at = |days: i64| Duration::from_secs((days * DAY - offset).max(0) as u64);
+
+        (at(start), at(end))
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl UtcOffset {
+    #[allow(dead_code)]
+    pub fn new(hours: i8, minutes: u8) -> Self {
+        let seconds = hours.unsigned_abs() as i64 * 3600 + minutes as i64 * 60;
+
+        Self {
+            seconds: if hours < 0 { -seconds } else { seconds },
+        }
+    }
+}
+
+impl FromStr for UtcOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid utc offset: {}", s);
+
+        let (sign, rest) = match s.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+        let hours: i64 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 14 || minutes > 59 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            seconds: sign * (hours * 3600 + minutes * 60),
+        })
+    }
+}
+
+impl TryFrom<String> for UtcOffset {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// The quotas are stored under their names,
+// so no two quotas can have the same name.
+pub fn unique<'de, D>(deserializer: D) -> Result<Option<Vec<Quota>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let quotas = Option::<Vec<Quota>>::deserialize(deserializer)?;
+
+    let mut names = HashSet::new();
+    for quota in quotas.iter().flatten() {
+        if !names.insert(quota.name.as_str()) {
+            return Err(de::Error::custom(format!(
+                "duplicate quota name: {}",
+                quota.name
+            )));
+        }
+    }
+
+    Ok(quotas)
+}
+
+// The year and the month of the day, counted from the
+// unix epoch. See http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month)
+}
+
+// The first day of the month, counted from the unix epoch.
+fn days_from_civil(year: i64, month: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-02-29T10:00:00Z, a thursday.
+    const LEAP_DAY: Duration = Duration::from_secs(1709200800);
+
+    fn secs(bounds: (Duration, Duration)) -> (u64, u64) {
+        (bounds.0.as_secs(), bounds.1.as_secs())
+    }
+
+    #[test]
+    fn test_bounds() {
+        let day = Quota::new("daily", Period::Day, 100);
+        // 2024-02-29 to 2024-03-01.
+        assert_eq!(secs(day.bounds(LEAP_DAY)), (1709164800, 1709251200));
+
+        let week = Quota::new("weekly", Period::Week, 100);
+        // monday 2024-02-26 to monday 2024-03-04.
+        assert_eq!(secs(week.bounds(LEAP_DAY)), (1708905600, 1709510400));
+
+        let month = Quota::new("monthly", Period::Month, 100);
+        // 2024-02-01 to 2024-03-01.
+        assert_eq!(secs(month.bounds(LEAP_DAY)), (1706745600, 1709251200));
+
+        // 2023-12-31T23:00:00Z is still in december.
+        let new_years_eve = Duration::from_secs(1704063600);
+        assert_eq!(secs(month.bounds(new_years_eve)), (1701388800, 1704067200));
+    }
+
+    #[test]
+    fn test_utc_offset() {
+        // 2024-02-29T10:00:00Z is already 2024-03-01 in UTC+14.
+        let month =
+            Quota::new("monthly", Period::Month, 100).with_utc_offset(UtcOffset::new(14, 0));
+        assert_eq!(
+            secs(month.bounds(LEAP_DAY)),
+            (1709251200 - 14 * 3600, 1711929600 - 14 * 3600)
+        );
+
+        assert_eq!("+05:30".parse::<UtcOffset>(), Ok(UtcOffset::new(5, 30)));
+        assert_eq!("-08:00".parse::<UtcOffset>(), Ok(UtcOffset::new(-8, 0)));
+        assert!("05:30".parse::<UtcOffset>().is_err());
+        assert!("+25:00".parse::<UtcOffset>().is_err());
+    }
+}
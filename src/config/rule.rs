@@ -0,0 +1,193 @@
+use std::{collections::HashSet, net::IpAddr, time::Duration};
+
+use serde::{de, Deserialize, Deserializer};
+
+use super::{Cidr, PathPattern};
+use crate::algorithm::{Algorithm, Rate};
+
+// A named limit for the requests that match all of its
+// criteria, written as a `[[rules]]` table. A rule without
+// any criteria matches every request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    name: String,
+    paths: Option<Vec<PathPattern>>,
+    methods: Option<Vec<String>>,
+    // Addresses or ranges the requests are sent from.
+    ip_addresses: Option<Vec<Cidr>>,
+    // The global algorithm and window time are
+    // used if the rule doesn't have its own.
+    algorithm: Option<Algorithm>,
+    limit: u32,
+    window_time: Option<u16>,
+    // How many requests a matching request counts as,
+    // unless the request has a cost of its own.
+    cost: Option<u32>,
+}
+
+impl Rule {
+    #[allow(dead_code)]
+    pub fn new<T: Into<String>>(name: T, limit: u32, window_time: u16) -> Self {
+        Self {
+            name: name.into(),
+            paths: None,
+            methods: None,
+            ip_addresses: None,
+            algorithm: None,
+            limit,
+            window_time: Some(window_time),
+            cost: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_paths<I, T>(mut self, paths: I) -> Self
+    where
+        T: Into<PathPattern>,
+        I: IntoIterator<Item = T>,
+    {
+        self.paths = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_methods<I, T>(mut self, methods: I) -> Self
+    where
+        T: Into<String>,
+        I: IntoIterator<Item = T>,
+    {
+        self.methods = Some(methods.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_ip_addresses<I, T>(mut self, ip_addresses: I) -> Self
+    where
+        T: Into<Cidr>,
+        I: IntoIterator<Item = T>,
+    {
+        self.ip_addresses = Some(ip_addresses.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_cost(mut self, cost: u32) -> Self {
+        self.cost = Some(cost);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cost(&self) -> Option<u32> {
+        self.cost
+    }
+
+    // Whether the request matches every criteria of the rule,
+    // a request without a path, method or ip address doesn't
+    // match the rules that expect one.
+    pub fn matches(&self, path: Option<&str>, method: Option<&str>, ip: Option<IpAddr>) -> bool {
+        if let Some(ref paths) = self.paths {
+            match path {
+                Some(path) if paths.iter().any(|pattern| pattern.is_match(path)) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(ref methods) = self.methods {
+            match method {
+                Some(method) if methods.iter().any(|m| m.eq_ignore_ascii_case(method)) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(ref ip_addresses) = self.ip_addresses {
+            match ip {
+                Some(ip) if ip_addresses.iter().any(|range| range.contains(ip)) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    pub fn rate(&self, algorithm: Algorithm, window_time: u16) -> Rate {
+        let window_time = self.window_time.unwrap_or(window_time);
+
+        Rate::new(
+            self.algorithm.unwrap_or(algorithm),
+            self.limit,
+            Duration::from_secs(window_time as u64),
+        )
+    }
+}
+
+// The rules are stored under their names,
+// so no two rules can have the same name.
+pub fn unique<'de, D>(deserializer: D) -> Result<Option<Vec<Rule>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rules = Option::<Vec<Rule>>::deserialize(deserializer)?;
+
+    let mut names = HashSet::new();
+    for rule in rules.iter().flatten() {
+        if !names.insert(rule.name.as_str()) {
+            return Err(de::Error::custom(format!(
+                "duplicate rule name: {}",
+                rule.name
+            )));
+        }
+    }
+
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_matches() {
+        let rule = Rule::new("login", 5, 60)
+            .with_paths(vec!["/login", "/users/*/login"])
+            .with_methods(vec!["POST"])
+            .with_ip_addresses(vec!["10.27.0.0/16".parse::<Cidr>().unwrap()]);
+
+        assert!(rule.matches(Some("/login"), Some("post"), ip("10.27.0.1")));
+        assert!(rule.matches(Some("/users/1/login"), Some("POST"), ip("10.27.0.1")));
+        assert!(!rule.matches(Some("/logout"), Some("POST"), ip("10.27.0.1")));
+        assert!(!rule.matches(Some("/login"), Some("GET"), ip("10.27.0.1")));
+        assert!(!rule.matches(Some("/login"), Some("POST"), ip("10.28.0.1")));
+        assert!(!rule.matches(Some("/login"), None, ip("10.27.0.1")));
+
+        // a rule without criteria matches every request.
+        assert!(Rule::new("everything", 5, 60).matches(None, None, None));
+    }
+
+    #[test]
+    fn test_rate() {
+        let rule = Rule::new("login", 5, 60);
+        assert_eq!(
+            rule.rate(Algorithm::Gcra, 300),
+            Rate::new(Algorithm::Gcra, 5, Duration::from_secs(60))
+        );
+
+        let rule = rule.with_algorithm(Algorithm::FixedWindow);
+        assert_eq!(
+            rule.rate(Algorithm::Gcra, 300).algorithm,
+            Algorithm::FixedWindow
+        );
+    }
+}
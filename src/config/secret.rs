@@ -0,0 +1,25 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+// A secret from the config, kept out of its debug
+// output since the whole config is printed on start.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new<T: Into<String>>(secret: T) -> Self {
+        Self(secret.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
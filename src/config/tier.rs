@@ -0,0 +1,124 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use serde::Deserialize;
+
+// A tier of ids with a limit and window time of their
+// own, written as a `[tiers.<name>]` table.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tier {
+    limit: u32,
+    // The global window time is used otherwise.
+    window_time: Option<u16>,
+    // The keys of the ids that are in the tier.
+    ids: Option<Vec<String>>,
+}
+
+impl Tier {
+    #[allow(dead_code)]
+    pub fn new(limit: u32, window_time: u16) -> Self {
+        Self {
+            limit,
+            window_time: Some(window_time),
+            ids: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_ids<I, T>(mut self, ids: I) -> Self
+    where
+        T: Into<String>,
+        I: IntoIterator<Item = T>,
+    {
+        self.ids = Some(ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    pub fn window_time(&self) -> Option<u16> {
+        self.window_time
+    }
+}
+
+// The tiers by their names, and the tier of every id
+// listed in one, so they are looked up in a single step.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "HashMap<String, Tier>")]
+pub struct Tiers {
+    tiers: HashMap<String, Tier>,
+    ids: HashMap<String, String>,
+}
+
+impl Tiers {
+    // Fails if an id is listed in more than one tier.
+    pub fn new(tiers: HashMap<String, Tier>) -> Result<Self, String> {
+        let mut ids = HashMap::new();
+        for (name, tier) in &tiers {
+            for id in tier.ids.iter().flatten() {
+                if let Some(other) = ids.insert(id.clone(), name.clone()) {
+                    return Err(format!(
+                        "id {} is in both the {} and {} tiers",
+                        id, other, name
+                    ));
+                }
+            }
+        }
+
+        Ok(Self { tiers, ids })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tier> {
+        self.tiers.get(name)
+    }
+
+    // The name of the tier the id is listed in.
+    pub fn tier_of(&self, key: &str) -> Option<&str> {
+        self.ids.get(key).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tier)> {
+        self.tiers.iter().map(|(name, tier)| (name.as_str(), tier))
+    }
+}
+
+impl TryFrom<HashMap<String, Tier>> for Tiers {
+    type Error = String;
+
+    fn try_from(tiers: HashMap<String, Tier>) -> Result<Self, Self::Error> {
+        Tiers::new(tiers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tier_of() {
+        let mut tiers = HashMap::new();
+        tiers.insert("free".to_owned(), Tier::new(100, 3600));
+        tiers.insert(
+            "pro".to_owned(),
+            Tier::new(1000, 3600).with_ids(vec!["acme", "1234"]),
+        );
+        let tiers = Tiers::new(tiers).unwrap();
+
+        assert_eq!(tiers.tier_of("acme"), Some("pro"));
+        assert_eq!(tiers.tier_of("1234"), Some("pro"));
+        assert_eq!(tiers.tier_of("globex"), None);
+        assert_eq!(tiers.get("free").map(Tier::limit), Some(100));
+
+        let mut tiers = HashMap::new();
+        tiers.insert(
+            "free".to_owned(),
+            Tier::new(100, 3600).with_ids(vec!["acme"]),
+        );
+        tiers.insert(
+            "pro".to_owned(),
+            Tier::new(1000, 3600).with_ids(vec!["acme"]),
+        );
+        assert!(Tiers::new(tiers).is_err());
+    }
+}
@@ -1,6 +1,98 @@
-use std::time::SystemTime;
+use std::{
+    error::Error,
+    time::{Duration, SystemTime},
+};
 
-use crate::{Backend, Config, IpAndPath};
+use crate::{
+    algorithm::{Algorithm, Outcome, Rate, Reason},
+    config::{AccessList, Concurrency, Period, Quota, Rule, Tier},
+    helpers::nonce,
+    Backend, Ban, Config, Counts, IpAndPath, Override, Usage, Windows,
+};
+
+// The outcome of a request, and the outcomes of the rules it
+// matched. The request is allowed only if all of them allow it.
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub outcome: Outcome,
+    pub rules: Vec<RuleOutcome>,
+    // The limited path pattern the request matched.
+    pub matched_path: Option<String>,
+    // The limit of the id, if the request got that far.
+    pub limit: Option<u32>,
+    // The quotas of the id, and what is left of them.
+    pub quotas: Vec<RuleOutcome>,
+}
+
+impl From<Outcome> for Decision {
+    fn from(outcome: Outcome) -> Self {
+        Self {
+            outcome,
+            rules: Vec::new(),
+            matched_path: None,
+            limit: None,
+            quotas: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleOutcome {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+// How much of a quota an id used in the current period.
+#[derive(Debug, Clone)]
+pub struct QuotaUsage {
+    pub name: String,
+    pub period: Period,
+    pub limit: u64,
+    pub used: u64,
+    pub resets_at: Duration,
+}
+
+// The rate an id is limited with and what is left of it. Only
+// the sliding window log keeps the requests themselves, along
+// with the ip addresses and the paths they were sent from and to.
+#[derive(Debug, Clone)]
+pub struct IdUsage {
+    pub rate: Rate,
+    pub remaining: u32,
+    pub requests: Option<Usage>,
+}
+
+// A lease on one of the requests an id can have in flight,
+// held until it is released or it expires.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub token: String,
+    pub expires_at: Duration,
+}
+
+// The outcome of acquiring a lease, and the lease
+// if the request is allowed.
+#[derive(Debug, Clone)]
+pub struct LeaseOutcome {
+    pub outcome: Outcome,
+    pub limit: u32,
+    pub lease: Option<Lease>,
+}
+
+// How many leases of a concurrency limiter an id holds.
+#[derive(Debug, Clone)]
+pub struct LeaseUsage {
+    pub name: String,
+    pub limit: u32,
+    pub held: usize,
+}
+
+// A request counted against a limit while it's decided,
+// given back if another limit denies it.
+enum Charge {
+    State(String, Rate),
+    Counter(String, u64),
+}
 
 #[derive(Debug, Clone)]
 pub struct Dur<T> {
@@ -14,79 +106,838 @@ where
 {
     pub fn new(backend: T, config: Option<Config>) -> Self {
         Self {
-            backend: backend,
+            backend,
             config: config.unwrap_or_default(),
         }
     }
 
-    pub fn request(&mut self, id: u64, ip_and_path: IpAndPath) -> (bool, usize) {
-        match self.backend.insert(id, ip_and_path.clone()) {
-            Ok(v) => {
-                let current_timestamp = SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap();
+    pub fn request(&self, id: u64, ip_and_path: IpAndPath) -> Decision {
+        self.evaluate(id, ip_and_path, false)
+    }
+
+    // Decides the request the same way, without counting it
+    // against any of the limits. Checks whether the request
+    // would be allowed, without using up the quota of the id.
+    pub fn check(&self, id: u64, ip_and_path: IpAndPath) -> Decision {
+        self.evaluate(id, ip_and_path, true)
+    }
 
-                self.backend.evict_older_timestamps(
-                    id,
-                    current_timestamp,
-                    self.config.window_time(),
-                );
+    fn evaluate(&self, id: u64, ip_and_path: IpAndPath, dry_run: bool) -> Decision {
+        let current_timestamp = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
 
-                let mut allow = true;
+        self.judge(id, ip_and_path, current_timestamp, dry_run)
+            .unwrap_or_else(|why| {
+                eprintln!("an error occured: {}", why);
+                Outcome::deny(None).into()
+            })
+    }
 
-                if (v as u32) > self.config.limit() {
-                    allow = false
+    // The requests on the deny and the allow lists, and then the
+    // ones of banned ids, are decided before any of the limits are
+    // checked, so they don't cost anything. Each limit checks and
+    // counts the request in a single step, so a request racing
+    // another one can't go over it.
+    fn judge(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        current_timestamp: Duration,
+        dry_run: bool,
+    ) -> Result<Decision, Box<dyn Error>> {
+        if let Some(decision) = self.listed(id, &ip_and_path) {
+            return Ok(decision);
+        }
+        if let Some(retry_after) = self.banned_for(id, current_timestamp)? {
+            return Ok(Outcome::deny(Some(retry_after))
+                .because(Reason::Banned)
+                .into());
+        }
+
+        let decision = self.decide(id, ip_and_path, current_timestamp, dry_run)?;
+
+        if !dry_run && !decision.outcome.allowed {
+            self.penalize(id, current_timestamp)?;
+        }
+
+        Ok(decision)
+    }
+
+    // The decision for a request on the deny or the allow list,
+    // the deny list goes first. An allowed request is never
+    // limited, so it has the whole limit of the id left.
+    fn listed(&self, id: u64, ip_and_path: &IpAndPath) -> Option<Decision> {
+        let on = |list: Option<&AccessList>| {
+            list.is_some_and(|list| list.contains(id, ip_and_path.ip, ip_and_path.path.as_deref()))
+        };
+
+        if on(self.config.deny()) {
+            Some(Outcome::deny(None).because(Reason::Denylisted).into())
+        } else if on(self.config.allow()) {
+            Some(
+                Outcome {
+                    reason: Some(Reason::Allowlisted),
+                    ..Outcome::allow(self.limit_of(ip_and_path.tier.as_deref()))
                 }
+                .into(),
+            )
+        } else {
+            None
+        }
+    }
 
-                match self.config.limited_ip_addresses() {
-                    // TODO(ycd): properly test here.
-                    Some(ip_addrs) => match ip_and_path.ip {
-                        Some(ref ip) => {
-                            if ip_addrs.contains(&ip.clone()) {
-                                match self.config.ip_addresses_limit() {
-                                    Some(limit) => {
-                                        if (limit as usize)
-                                            < self.backend.ip_address_count(id, ip.clone())
-                                        {
-                                            allow = false
-                                        }
-                                    }
-                                    None => (),
-                                }
-                            }
-                        }
-                        None => (),
-                    },
-                    None => (),
+    // How long the id is still banned for, if it is. Ids
+    // are only looked up when there is a penalty.
+    fn banned_for(
+        &self,
+        id: u64,
+        current_timestamp: Duration,
+    ) -> Result<Option<Duration>, Box<dyn Error>> {
+        if self.config.penalty().is_none() {
+            return Ok(None);
+        }
+
+        Ok(self
+            .backend
+            .get_ban(id, current_timestamp)?
+            .filter(|ban| ban.is_active(current_timestamp))
+            .map(|ban| Duration::from_millis(ban.until).saturating_sub(current_timestamp)))
+    }
+
+    // Counts the denied request against the penalty, and bans
+    // the id once it's denied too many times inside the window.
+    // The denials are counted in fixed windows, and only the
+    // denial that goes over the limit first bans the id. A ban
+    // that follows one the id is still remembered for lasts
+    // twice as long.
+    fn penalize(&self, id: u64, current_timestamp: Duration) -> Result<(), Box<dyn Error>> {
+        let penalty = match self.config.penalty() {
+            Some(penalty) => penalty,
+            None => return Ok(()),
+        };
+        let window_time = penalty.window_time().as_millis();
+        let window_end = (current_timestamp.as_millis() / window_time + 1) * window_time;
+
+        let denials = self.backend.add(
+            penalty_key(id),
+            1,
+            u64::MAX,
+            current_timestamp,
+            Duration::from_millis(window_end as u64),
+        )?;
+        if denials != penalty.denials() as u64 + 1 {
+            return Ok(());
+        }
+
+        let offences = match self.backend.get_ban(id, current_timestamp)? {
+            Some(ban) => ban.offences.saturating_add(1),
+            None => 1,
+        };
+        let until = current_timestamp + penalty.ban_time(offences);
+        let ban = Ban {
+            until: until.as_millis() as u64,
+            offences,
+        };
+
+        self.backend
+            .set_ban(id, ban, current_timestamp, until + penalty.max_ban_time())
+    }
+
+    // The maximum number of requests an id can send
+    // under the configured algorithm.
+    pub fn limit(&self) -> u32 {
+        self.limit_of(None)
+    }
+
+    // The same, for the ids in the tier.
+    pub fn limit_of(&self, tier: Option<&str>) -> u32 {
+        let tier = tier.and_then(|name| self.config.tier(name));
+
+        self.config.rate_of(tier).limit
+    }
+
+    // The limit of the id, the limit of its override
+    // if it has one.
+    pub fn limit_of_id(&self, id: u64, tier: Option<&str>) -> Result<u32, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        Ok(self.rate_of(id, tier, current_timestamp)?.limit)
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.backend.is_blocking()
+    }
+
+    // The rate the id is limited with. The override of the id
+    // comes first, then the tier of the id and the global limit.
+    fn rate_of(
+        &self,
+        id: u64,
+        tier: Option<&str>,
+        current_timestamp: Duration,
+    ) -> Result<Rate, Box<dyn Error>> {
+        let rate = match self.backend.get_override(id, current_timestamp)? {
+            Some(limit) => self
+                .config
+                .rate_of(Some(&Tier::new(limit.limit, limit.window_time))),
+            None => self
+                .config
+                .rate_of(tier.and_then(|name| self.config.tier(name))),
+        };
+
+        Ok(rate)
+    }
+
+    // The rate the id is limited with, and what is left of it.
+    // The other algorithms only keep a state, which is peeked
+    // at for a request as if it was sent now.
+    pub fn usage(&self, id: u64, tier: Option<&str>) -> Result<IdUsage, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+        let rate = self.rate_of(id, tier, current_timestamp)?;
+
+        if rate.algorithm != Algorithm::SlidingWindowLog {
+            let outcome = self.backend.peek(id.to_string(), current_timestamp, rate)?;
+
+            return Ok(IdUsage {
+                rate,
+                // the peeked request would have used up its cost.
+                remaining: match outcome.allowed {
+                    true => outcome.remaining.saturating_add(rate.cost),
+                    false => 0,
+                },
+                requests: None,
+            });
+        }
+
+        let windows = Windows {
+            id: rate.period.as_secs() as u16,
+            ..self.windows()
+        };
+        let usage = self.backend.usage(id, current_timestamp, windows)?;
+
+        Ok(IdUsage {
+            rate,
+            remaining: rate.limit.saturating_sub(usage.requests as u32),
+            requests: Some(usage),
+        })
+    }
+
+    // Forgets every request of the id.
+    pub fn reset(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.backend.reset(id)
+    }
+
+    // Limits the id with the given limit until the ttl
+    // runs out, instead of its tier or the global limit.
+    pub fn override_limit(
+        &self,
+        id: u64,
+        limit: Override,
+        ttl: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.backend.set_override(id, limit, current_timestamp, ttl)
+    }
+
+    // How much of every quota the id used in the current period.
+    pub fn quota_usage(&self, id: u64) -> Result<Vec<QuotaUsage>, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.config
+            .quotas()
+            .iter()
+            .map(|quota| {
+                Ok(QuotaUsage {
+                    name: quota.name().to_owned(),
+                    period: quota.period(),
+                    limit: quota.limit(),
+                    used: self
+                        .backend
+                        .counter(quota_key(id, quota), current_timestamp)?,
+                    resets_at: quota.bounds(current_timestamp).1,
+                })
+            })
+            .collect()
+    }
+
+    // Takes a lease from the concurrency limiter with the name for
+    // the id, unless it already holds the limit of them. Returns
+    // None if there is no such limiter.
+    // The deny and the allow lists, and the bans, go first the
+    // same way they do for requests. An allowlisted id isn't
+    // capped, but its leases are still held until they are
+    // released.
+    pub fn acquire_lease(
+        &self,
+        id: u64,
+        ip_and_path: &IpAndPath,
+        name: &str,
+    ) -> Result<Option<LeaseOutcome>, Box<dyn Error>> {
+        let limiter = match self.config.concurrency(name) {
+            Some(limiter) => limiter,
+            None => return Ok(None),
+        };
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+        let limit = limiter.limit() as usize;
+
+        let listed = self
+            .listed(id, ip_and_path)
+            .map(|decision| decision.outcome);
+        let denied = match listed {
+            Some(outcome) if !outcome.allowed => Some(outcome),
+            Some(_) => None,
+            None => self
+                .banned_for(id, current_timestamp)?
+                .map(|retry_after| Outcome::deny(Some(retry_after)).because(Reason::Banned)),
+        };
+        if let Some(outcome) = denied {
+            return Ok(Some(LeaseOutcome {
+                outcome,
+                limit: limiter.limit(),
+                lease: None,
+            }));
+        }
+
+        let expires_at = current_timestamp + limiter.ttl();
+        let token = format!("{}:{:016x}:{}", id, nonce(), limiter.name());
+        let held = self.backend.take_lease(
+            lease_key(id, limiter),
+            token.clone(),
+            if listed.is_some() { usize::MAX } else { limit },
+            current_timestamp,
+            expires_at,
+        )?;
+        // the leases are given back by the clients, so there
+        // is no telling when the next one is free.
+        let outcome = match (listed, limit.checked_sub(held)) {
+            (Some(outcome), remaining) => Outcome {
+                remaining: remaining.unwrap_or_default() as u32,
+                ..outcome
+            },
+            (None, Some(remaining)) => Outcome::allow(remaining as u32),
+            (None, None) => Outcome::deny(None).because(Reason::Concurrency),
+        };
+
+        Ok(Some(LeaseOutcome {
+            lease: match outcome.allowed {
+                true => Some(Lease { token, expires_at }),
+                false => None,
+            },
+            outcome,
+            limit: limiter.limit(),
+        }))
+    }
+
+    // Gives back the lease of the token. Returns whether it
+    // was still held, the expired leases are already reclaimed.
+    pub fn release_lease(&self, token: &str) -> Result<bool, Box<dyn Error>> {
+        // the token is made of the id, a nonce and the name of the limiter.
+        let mut parts = token.splitn(3, ':');
+        let (id, name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(_), Some(name)) => (id.parse::<u64>(), name),
+            _ => return Ok(false),
+        };
+        let (id, limiter) = match (id, self.config.concurrency(name)) {
+            (Ok(id), Some(limiter)) => (id, limiter),
+            _ => return Ok(false),
+        };
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.backend
+            .release_lease(lease_key(id, limiter), token.to_owned(), current_timestamp)
+    }
+
+    // How many leases of every concurrency limiter the id holds.
+    pub fn lease_usage(&self, id: u64) -> Result<Vec<LeaseUsage>, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.config
+            .concurrency_limiters()
+            .iter()
+            .map(|limiter| {
+                Ok(LeaseUsage {
+                    name: limiter.name().to_owned(),
+                    limit: limiter.limit(),
+                    held: self
+                        .backend
+                        .lease_count(lease_key(id, limiter), current_timestamp)?,
+                })
+            })
+            .collect()
+    }
+
+    // The ban of the id, if it's banned.
+    pub fn ban(&self, id: u64) -> Result<Option<Ban>, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        Ok(self
+            .backend
+            .get_ban(id, current_timestamp)?
+            .filter(|ban| ban.is_active(current_timestamp)))
+    }
+
+    // The ids that are banned, along with their bans.
+    pub fn bans(&self) -> Result<Vec<(u64, Ban)>, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.backend.bans(current_timestamp)
+    }
+
+    // Lifts the ban of the id, and forgets its earlier offences
+    // too. Returns whether the id was banned.
+    pub fn lift_ban(&self, id: u64) -> Result<bool, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.backend.lift_ban(id, current_timestamp)
+    }
+
+    pub fn limit_override(&self, id: u64) -> Result<Option<Override>, Box<dyn Error>> {
+        let current_timestamp = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        self.backend.get_override(id, current_timestamp)
+    }
+
+    // Drops the ids and states that expired by now from the
+    // backend, returns how many were dropped.
+    pub fn sweep(&self) -> usize {
+        let current_timestamp = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+
+        // the ids in a tier can have a longer window.
+        let window_time = self
+            .config
+            .tiers()
+            .filter_map(|(_, tier)| tier.window_time())
+            .fold(self.windows().longest(), u16::max);
+
+        self.backend.sweep(current_timestamp, window_time)
+    }
+
+    // The window times of the id, and of its limited
+    // ip addresses and paths if they have their own.
+    fn windows(&self) -> Windows {
+        let window_time = self.config.window_time();
+
+        Windows {
+            id: window_time,
+            ip: self
+                .config
+                .ip_addresses_window_time()
+                .unwrap_or(window_time),
+            path: self.config.path_window_time().unwrap_or(window_time),
+        }
+    }
+
+    // Requests from more ip addresses than the id is allowed
+    // to use are denied, before they are counted against any
+    // of the limits. Requests from a limited ip range are then
+    // counted as the address the range is limited as, and the
+    // requests to a limited path as the path it's limited as.
+    // The rules are matched against the request as it was sent.
+    // The request is charged its own cost against every limit,
+    // or the highest cost of the rules it matches if it doesn't
+    // have one. On a dry run the backend is only read, never
+    // updated. Unless the denied requests are counted too, the
+    // request is given back to every limit that counted it once
+    // another one denies it. The algorithms other than the log
+    // never count a request they deny themselves.
+    fn decide(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        current_timestamp: Duration,
+        dry_run: bool,
+    ) -> Result<Decision, Box<dyn Error>> {
+        let network = ip_and_path.ip.map(|ip| self.config.network(ip));
+
+        if let Some(ip) = network {
+            let window_time = self.config.window_time();
+            let limit = self.config.ip_addr_limit() as usize;
+            let recorded = if dry_run {
+                self.backend
+                    .peek_ip_address(id, ip, current_timestamp, window_time, limit)?
+            } else {
+                self.backend
+                    .record_ip_address(id, ip, current_timestamp, window_time, limit)?
+            };
+            if !recorded {
+                return Ok(Outcome::deny(None)
+                    .because(Reason::UniqueIpAddresses)
+                    .into());
+            }
+        }
+
+        let limited_ip = ip_and_path
+            .ip
+            .and_then(|ip| self.config.limited_ip_address(ip));
+        let (matched_path, limited_path) = match ip_and_path.path {
+            Some(ref path) => self
+                .config
+                .limited_path(path, ip_and_path.method.as_deref())
+                .map(|(pattern, path)| (Some(pattern.to_string()), Some(path)))
+                .unwrap_or_default(),
+            None => (None, None),
+        };
+        let matched: Vec<&Rule> = self
+            .config
+            .rules()
+            .iter()
+            .filter(|rule| {
+                rule.matches(
+                    ip_and_path.path.as_deref(),
+                    ip_and_path.method.as_deref(),
+                    ip_and_path.ip,
+                )
+            })
+            .collect();
+        let cost = ip_and_path
+            .cost
+            .or_else(|| matched.iter().filter_map(|rule| rule.cost()).max())
+            .unwrap_or(1)
+            .max(1);
+        let reserve = !dry_run && !self.config.count_rejected();
+        let mut charges = Vec::new();
+        let rules = self.rules(id, &matched, cost, current_timestamp, dry_run, &mut charges)?;
+
+        let rate = self
+            .rate_of(id, ip_and_path.tier.as_deref(), current_timestamp)?
+            .with_cost(cost);
+
+        let limited = (limited_ip.is_some(), limited_path.is_some());
+        let ip_and_path = IpAndPath {
+            ip: limited_ip.or(network),
+            path: limited_path.or(ip_and_path.path),
+            cost: Some(cost),
+            ..ip_and_path
+        };
+
+        let log = self.config.algorithm() == Algorithm::SlidingWindowLog;
+        let mut outcome = match self.config.algorithm() {
+            // a request can't be taken out of the log again, so
+            // it's only logged once every other limit allowed it.
+            Algorithm::SlidingWindowLog if reserve => Outcome::allow(rate.limit),
+            Algorithm::SlidingWindowLog => self.sliding_window_log(
+                id,
+                ip_and_path.clone(),
+                limited,
+                rate,
+                current_timestamp,
+                dry_run,
+            )?,
+            _ => self.acquire(
+                id,
+                ip_and_path.clone(),
+                limited,
+                rate,
+                current_timestamp,
+                dry_run,
+                &mut charges,
+            )?,
+        };
+        for rule in &rules {
+            outcome = combine(outcome, rule.outcome);
+        }
+
+        let charge = outcome.allowed && !dry_run;
+        let quotas = self.quotas(id, cost, charge, current_timestamp, &mut charges)?;
+        for quota in &quotas {
+            outcome = combine(outcome, quota.outcome);
+        }
+
+        if log && reserve {
+            let logged = self.sliding_window_log(
+                id,
+                ip_and_path,
+                limited,
+                rate,
+                current_timestamp,
+                !outcome.allowed,
+            )?;
+            outcome = combine(logged, outcome);
+        }
+        if reserve && !outcome.allowed {
+            self.give_back(charges, current_timestamp)?;
+        }
+
+        Ok(Decision {
+            outcome,
+            rules,
+            matched_path,
+            limit: Some(rate.limit),
+            quotas,
+        })
+    }
+
+    // Counts the request against the state of every rule it
+    // matches, each with its own limit and window time.
+    fn rules(
+        &self,
+        id: u64,
+        rules: &[&Rule],
+        cost: u32,
+        current_timestamp: Duration,
+        dry_run: bool,
+        charges: &mut Vec<Charge>,
+    ) -> Result<Vec<RuleOutcome>, Box<dyn Error>> {
+        rules
+            .iter()
+            .map(|rule| {
+                let rate = rule
+                    .rate(self.config.algorithm(), self.config.window_time())
+                    .with_cost(cost);
+                let outcome = self
+                    .acquire_key(
+                        format!("{}:rule:{}", id, rule.name()),
+                        current_timestamp,
+                        rate,
+                        dry_run,
+                        charges,
+                    )?
+                    .because(Reason::Rule);
+
+                Ok(RuleOutcome {
+                    name: rule.name().to_owned(),
+                    outcome,
+                })
+            })
+            .collect()
+    }
+
+    // Charges the cost of an allowed request to every quota, each
+    // in a single step. The quotas charged before one that has no
+    // room left for it are given back, so the request is charged
+    // to all of them or to none. The other requests are only
+    // checked against the quotas, as if they were charged.
+    fn quotas(
+        &self,
+        id: u64,
+        cost: u32,
+        charge: bool,
+        current_timestamp: Duration,
+        charges: &mut Vec<Charge>,
+    ) -> Result<Vec<RuleOutcome>, Box<dyn Error>> {
+        let quotas = self.config.quotas();
+        let cost = cost as u64;
+
+        let mut fits = true;
+        let mut charged = Vec::new();
+        let mut counts = Vec::with_capacity(quotas.len());
+        for quota in quotas {
+            let key = quota_key(id, quota);
+            let count = if charge && fits {
+                let (_, resets_at) = quota.bounds(current_timestamp);
+                let count = self.backend.add(
+                    key.clone(),
+                    cost,
+                    quota.limit(),
+                    current_timestamp,
+                    resets_at,
+                )?;
+                if count <= quota.limit() {
+                    charged.push(key);
                 }
+                count
+            } else {
+                self.backend.counter(key, current_timestamp)? + cost
+            };
 
-                match self.config.limited_paths() {
-                    Some(paths) => match ip_and_path.path {
-                        Some(path) => {
-                            if paths.contains(&path.clone()) {
-                                match self.config.path_limit() {
-                                    Some(limit) => {
-                                        if (limit as usize) < self.backend.path_count(id, path) {
-                                            allow = false
-                                        }
-                                    }
-                                    None => (),
-                                }
-                            }
-                        }
-                        None => (),
-                    },
-                    None => (),
+            fits = fits && count <= quota.limit();
+            counts.push(count);
+        }
+        for key in charged {
+            match fits {
+                true => charges.push(Charge::Counter(key, cost)),
+                false => self.backend.subtract(key, cost, current_timestamp)?,
+            }
+        }
+
+        let outcomes = quotas
+            .iter()
+            .zip(counts)
+            .map(|(quota, count)| {
+                let (_, resets_at) = quota.bounds(current_timestamp);
+                let outcome = match quota.limit().checked_sub(count) {
+                    Some(remaining) => Outcome::allow(remaining.min(u32::MAX as u64) as u32),
+                    // a request that costs more than the quota is never allowed.
+                    None if cost > quota.limit() => Outcome::deny(None),
+                    None => Outcome::deny(Some(resets_at.saturating_sub(current_timestamp))),
+                };
+
+                RuleOutcome {
+                    name: quota.name().to_owned(),
+                    outcome: outcome.because(Reason::Quota),
                 }
+            })
+            .collect();
+
+        Ok(outcomes)
+    }
+
+    fn sliding_window_log(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        (ip_limited, path_limited): (bool, bool),
+        rate: Rate,
+        current_timestamp: Duration,
+        dry_run: bool,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let windows = Windows {
+            id: rate.period.as_secs() as u16,
+            ..self.windows()
+        };
+        let limit = |limited: bool, limit: Option<u32>| match (limited, limit) {
+            (true, Some(limit)) => limit as usize,
+            _ => usize::MAX,
+        };
+        let limits = Counts {
+            requests: rate.limit as usize,
+            ip: limit(ip_limited, self.config.ip_addresses_limit()),
+            path: limit(path_limited, self.config.path_limit()),
+        };
+
+        let counts = if dry_run {
+            self.backend
+                .peek_record(id, ip_and_path, current_timestamp, windows)?
+        } else {
+            let limits = Some(limits).filter(|_| !self.config.count_rejected());
+            self.backend
+                .record(id, ip_and_path, current_timestamp, windows, limits)?
+        };
+
+        let mut reason = None;
+
+        if counts.path > limits.path {
+            reason = Some(Reason::PathLimit)
+        }
 
-                (allow, v)
+        if counts.ip > limits.ip {
+            reason = Some(Reason::IpAddressLimit)
+        }
+
+        if counts.requests > limits.requests {
+            reason = Some(Reason::Limit)
+        }
+
+        Ok(Outcome {
+            allowed: reason.is_none(),
+            reason,
+            ..Outcome::allow(rate.limit.saturating_sub(counts.requests as u32))
+        })
+    }
+
+    // Counts the request against the state of the id, and
+    // against the states of the limited path and ip address
+    // if there are any. Path and ip address states are limited
+    // with their own limit and window time.
+    #[allow(clippy::too_many_arguments)]
+    fn acquire(
+        &self,
+        id: u64,
+        ip_and_path: IpAndPath,
+        (ip_limited, path_limited): (bool, bool),
+        rate: Rate,
+        current_timestamp: Duration,
+        dry_run: bool,
+        charges: &mut Vec<Charge>,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let mut outcome =
+            self.acquire_key(id.to_string(), current_timestamp, rate, dry_run, charges)?;
+
+        if let (true, Some(ip)) = (ip_limited, ip_and_path.ip) {
+            if let Some(limit) = self.config.ip_addresses_limit() {
+                let window_time = self
+                    .config
+                    .ip_addresses_window_time()
+                    .unwrap_or_else(|| self.config.window_time());
+                let ip_rate = Rate::new(
+                    rate.algorithm,
+                    limit,
+                    Duration::from_secs(window_time as u64),
+                )
+                .with_cost(rate.cost);
+                let ip_outcome = self
+                    .acquire_key(
+                        format!("{}:ip:{}", id, ip),
+                        current_timestamp,
+                        ip_rate,
+                        dry_run,
+                        charges,
+                    )?
+                    .because(Reason::IpAddressLimit);
+                outcome = combine(outcome, ip_outcome);
             }
+        }
 
-            Err(why) => {
-                eprintln!("an error occured: {}", why);
-                (false, 0)
+        if let (true, Some(path)) = (path_limited, ip_and_path.path) {
+            if let Some(limit) = self.config.path_limit() {
+                let window_time = self
+                    .config
+                    .path_window_time()
+                    .unwrap_or_else(|| self.config.window_time());
+                let path_rate = Rate::new(
+                    rate.algorithm,
+                    limit,
+                    Duration::from_secs(window_time as u64),
+                )
+                .with_cost(rate.cost);
+                let path_outcome = self
+                    .acquire_key(
+                        format!("{}:path:{}", id, path),
+                        current_timestamp,
+                        path_rate,
+                        dry_run,
+                        charges,
+                    )?
+                    .because(Reason::PathLimit);
+                outcome = combine(outcome, path_outcome);
             }
         }
+
+        Ok(outcome)
+    }
+
+    // Counts the request against the state stored under
+    // the key, or only peeks at it on a dry run.
+    fn acquire_key(
+        &self,
+        key: String,
+        current_timestamp: Duration,
+        rate: Rate,
+        dry_run: bool,
+        charges: &mut Vec<Charge>,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        if dry_run {
+            return self.backend.peek(key, current_timestamp, rate);
+        }
+
+        let outcome = self.backend.acquire(key.clone(), current_timestamp, rate)?;
+        if outcome.allowed {
+            charges.push(Charge::State(key, rate));
+        }
+
+        Ok(outcome)
+    }
+
+    // Gives back what a denied request was charged.
+    fn give_back(
+        &self,
+        charges: Vec<Charge>,
+        current_timestamp: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        for charge in charges {
+            match charge {
+                Charge::State(key, rate) => self.backend.release(key, current_timestamp, rate)?,
+                Charge::Counter(key, amount) => {
+                    self.backend.subtract(key, amount, current_timestamp)?
+                }
+            }
+        }
+
+        Ok(())
     }
 
     pub fn remaning_requests(&self, id: u64) -> u32 {
@@ -94,13 +945,58 @@ where
     }
 }
 
+// The counter of the quota is stored under the id, so it's
+// dropped along with the other states of the id.
+fn quota_key(id: u64, quota: &Quota) -> String {
+    format!("{}:quota:{}", id, quota.name())
+}
+
+// The leases are stored under the id the same way.
+fn lease_key(id: u64, limiter: &Concurrency) -> String {
+    format!("{}:lease:{}", id, limiter.name())
+}
+
+// And so are the denials counted against the penalty. The
+// counter isn't evicted to make room for new ids, it lasts
+// until the end of the window.
+fn penalty_key(id: u64) -> String {
+    format!("{}:penalty", id)
+}
+
+// Combines the outcome of the id with the outcome of one of
+// its limited paths or ip addresses. The request is allowed
+// only if both allow it, it can be retried once both do and
+// it has to wait for the longer of the two delays.
+fn combine(outcome: Outcome, other: Outcome) -> Outcome {
+    Outcome {
+        allowed: outcome.allowed && other.allowed,
+        remaining: outcome.remaining,
+        retry_after: longest(outcome.retry_after, other.retry_after),
+        delay: longest(outcome.delay, other.delay),
+        reason: outcome.reason.or(other.reason),
+    }
+}
+
+fn longest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use std::thread::sleep;
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        thread::sleep,
+    };
 
     use super::*;
-    use crate::Memory;
+    use crate::{
+        config::{Ip, Limits, Path, Penalty, Rule, Tier, Tiers},
+        Memory,
+    };
 
     #[test]
     fn test_sliding_window_logs() {
@@ -120,4 +1016,845 @@ mod tests {
         dur.request(12938102, IpAndPath::new(None, None));
         assert_eq!(dur.backend.request_count(12938102), 1);
     }
+
+    #[test]
+    fn test_path_window_time() {
+        let limits = Limits::new(Some(Path::new(vec!["/abc"], 1, 1)), None);
+        let config = Config::new(Some(300), None, Some(300), None, None, limits);
+        let dur = Dur::new(Memory::new(), Some(config));
+
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/abc".to_owned()))
+                .outcome
+                .allowed
+        );
+        let outcome = dur
+            .request(12938102, IpAndPath::from_path("/abc".to_owned()))
+            .outcome;
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.reason, Some(Reason::PathLimit));
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/xyz".to_owned()))
+                .outcome
+                .allowed
+        );
+
+        // the path window is over, but the global one isn't.
+        sleep(std::time::Duration::from_secs(3));
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/abc".to_owned()))
+                .outcome
+                .allowed
+        );
+        assert_eq!(dur.backend.request_count(12938102), 4);
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        let path = Path::new(vec!["/users/*/orders", "/users/1/orders"], 1, 300).with_shared(false);
+        let config = Config::new(None, None, None, None, None, Limits::new(Some(path), None));
+        let dur = Dur::new(Memory::new(), Some(config));
+
+        // unless they share it, every path that matches
+        // a glob gets its own limit.
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/users/2/orders".to_owned()))
+                .outcome
+                .allowed
+        );
+        let outcome = dur
+            .request(12938102, IpAndPath::from_path("/users/2/orders".to_owned()))
+            .outcome;
+        assert_eq!(outcome.reason, Some(Reason::PathLimit));
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/users/3/orders".to_owned()))
+                .outcome
+                .allowed
+        );
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/users/3".to_owned()))
+                .outcome
+                .allowed
+        );
+        assert!(
+            dur.request(12938102, IpAndPath::from_path("/users/3".to_owned()))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_shared_path_patterns() {
+        // the paths share the limit by default.
+        let path = Path::new(vec![r"regex:^/users/\d+$"], 1, 300).with_methods(vec!["POST"]);
+        let config = Config::new(None, None, None, None, None, Limits::new(Some(path), None));
+        let dur = Dur::new(Memory::new(), Some(config));
+
+        let request = |path: &str, method: &str| {
+            IpAndPath::from_path(path.to_owned()).with_method(Some(method.to_owned()))
+        };
+        assert!(
+            dur.request(12938102, request("/users/1", "POST"))
+                .outcome
+                .allowed
+        );
+
+        // the paths that match the pattern share a single limit.
+        let outcome = dur.request(12938102, request("/users/2", "POST")).outcome;
+        assert_eq!(outcome.reason, Some(Reason::PathLimit));
+
+        // other methods aren't limited.
+        assert!(
+            dur.request(12938102, request("/users/2", "GET"))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_rules(vec![
+            Rule::new("login", 1, 60).with_paths(vec!["/login"]),
+            Rule::new("users", 2, 60)
+                .with_paths(vec!["/users/**"])
+                .with_algorithm(Algorithm::FixedWindow),
+            Rule::new("everything", 3, 60),
+        ]);
+
+        let decision = dur.request(12938102, IpAndPath::from_path("/login".to_owned()));
+        assert!(decision.outcome.allowed);
+        let names: Vec<&str> = decision
+            .rules
+            .iter()
+            .map(|rule| rule.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["login", "everything"]);
+
+        // every matching rule is checked, even when one fails.
+        let decision = dur.request(12938102, IpAndPath::from_path("/login".to_owned()));
+        assert!(!decision.outcome.allowed);
+        assert_eq!(decision.outcome.reason, Some(Reason::Rule));
+        assert!(!decision.rules[0].outcome.allowed);
+        assert!(decision.rules[0].outcome.retry_after.is_some());
+        assert!(decision.rules[1].outcome.allowed);
+        assert_eq!(decision.rules[1].outcome.remaining, 1);
+
+        let decision = dur.request(12938102, IpAndPath::from_path("/users/1".to_owned()));
+        assert!(decision.outcome.allowed);
+        assert_eq!(decision.rules[0].name, "users");
+        let decision = dur.request(12938102, IpAndPath::from_path("/users/2".to_owned()));
+        assert!(!decision.outcome.allowed);
+        assert_eq!(decision.rules[1].name, "everything");
+        assert!(!decision.rules[1].outcome.allowed);
+    }
+
+    #[test]
+    fn test_cost() {
+        for algorithm in [Algorithm::SlidingWindowLog, Algorithm::TokenBucket] {
+            let mut dur = Dur::new(Memory::new(), None);
+            dur.config.set_limit(10);
+            dur.config.set_algorithm(algorithm);
+            dur.config.set_rules(vec![Rule::new("export", 6, 60)
+                .with_paths(vec!["/export"])
+                .with_cost(3)]);
+
+            let request = |path: &str, cost| IpAndPath::from_path(path.to_owned()).with_cost(cost);
+
+            let outcome = dur.request(1, request("/orders", Some(4))).outcome;
+            assert_eq!((outcome.allowed, outcome.remaining), (true, 6));
+
+            // the request to /export costs 3 without a cost of its own.
+            let decision = dur.request(1, request("/export", None));
+            assert_eq!(decision.outcome.remaining, 3, "{:?}", algorithm);
+            assert_eq!(decision.rules[0].outcome.remaining, 3);
+            let decision = dur.request(1, request("/export", Some(1)));
+            assert_eq!(decision.rules[0].outcome.remaining, 2);
+
+            // the denied request doesn't use up the rest.
+            dur.config.set_count_rejected(false);
+            let outcome = dur.request(1, request("/orders", Some(3))).outcome;
+            assert!(!outcome.allowed);
+            assert!(dur.request(1, request("/orders", Some(2))).outcome.allowed);
+        }
+    }
+
+    #[test]
+    fn test_quotas() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_limit(10);
+        dur.config.set_quotas(vec![
+            Quota::new("daily", Period::Day, 3),
+            Quota::new("monthly", Period::Month, 100),
+        ]);
+
+        let request = |cost| IpAndPath::new(None, None).with_cost(Some(cost));
+        let decision = dur.request(1, request(2));
+        assert!(decision.outcome.allowed);
+        assert_eq!(decision.quotas[0].outcome.remaining, 1);
+        assert_eq!(decision.quotas[1].outcome.remaining, 98);
+
+        // neither quota is charged when one of them is used up.
+        let decision = dur.request(1, request(2));
+        assert_eq!(decision.outcome.reason, Some(Reason::Quota));
+        assert!(decision.quotas[0].outcome.retry_after.is_some());
+        assert!(dur.check(1, request(1)).outcome.allowed);
+        assert!(dur.request(1, request(1)).outcome.allowed);
+        assert!(!dur.request(1, request(1)).outcome.allowed);
+
+        let usage = dur.quota_usage(1).unwrap();
+        assert_eq!((usage[0].used, usage[1].used), (3, 3));
+        assert!(usage[0].resets_at <= usage[1].resets_at);
+
+        // the requests denied by the other limits aren't charged.
+        dur.config.set_limit(1);
+        assert!(!dur.request(2, request(2)).outcome.allowed);
+        assert_eq!(dur.quota_usage(2).unwrap()[0].used, 0);
+    }
+
+    #[test]
+    fn test_give_back() {
+        for algorithm in [
+            Algorithm::SlidingWindowLog,
+            Algorithm::TokenBucket,
+            Algorithm::SlidingWindowCounter,
+            Algorithm::Gcra,
+            Algorithm::FixedWindow,
+        ] {
+            let mut dur = Dur::new(Memory::new(), None);
+            dur.config.set_algorithm(algorithm);
+            dur.config.set_limit(4);
+            dur.config.set_count_rejected(false);
+            dur.config
+                .set_rules(vec![Rule::new("login", 1, 60).with_paths(vec!["/login"])]);
+            dur.config.set_quotas(vec![
+                Quota::new("monthly", Period::Month, 100),
+                Quota::new("daily", Period::Day, 2),
+            ]);
+            let request = |path: &str| IpAndPath::from_path(path.to_owned());
+
+            assert!(dur.request(1, request("/login")).outcome.allowed);
+            // the limit of the id gets back what the rule denied.
+            for _ in 0..3 {
+                let outcome = dur.request(1, request("/login")).outcome;
+                assert_eq!(outcome.reason, Some(Reason::Rule), "{}", algorithm);
+            }
+            assert!(dur.request(1, request("/a")).outcome.allowed);
+
+            // and the monthly quota what the daily one denied.
+            let outcome = dur.request(1, request("/a")).outcome;
+            assert_eq!(outcome.reason, Some(Reason::Quota), "{}", algorithm);
+            let usage = dur.quota_usage(1).unwrap();
+            assert_eq!((usage[0].used, usage[1].used), (2, 2));
+
+            dur.config
+                .set_quotas(vec![Quota::new("monthly", Period::Month, 100)]);
+            assert!(dur.request(1, request("/a")).outcome.allowed);
+            assert!(dur.request(1, request("/a")).outcome.allowed);
+            let outcome = dur.request(1, request("/a")).outcome;
+            assert_eq!(outcome.reason, Some(Reason::Limit), "{}", algorithm);
+            assert_eq!(dur.quota_usage(1).unwrap()[0].used, 4);
+        }
+    }
+
+    #[test]
+    fn test_tiers() {
+        let mut tiers = std::collections::HashMap::new();
+        tiers.insert("free".to_owned(), Tier::new(1, 300));
+        tiers.insert("pro".to_owned(), Tier::new(3, 300));
+
+        for algorithm in [Algorithm::SlidingWindowLog, Algorithm::FixedWindow] {
+            let mut dur = Dur::new(Memory::new(), None);
+            dur.config.set_limit(2);
+            dur.config.set_algorithm(algorithm);
+            dur.config.set_tiers(Tiers::new(tiers.clone()).unwrap());
+            assert_eq!(dur.limit_of(Some("pro")), 3);
+
+            let request = |tier: Option<&str>| {
+                IpAndPath::new(None, None).with_tier(tier.map(ToOwned::to_owned))
+            };
+            let allowed = |id, tier| {
+                (0..4)
+                    .take_while(|_| dur.request(id, request(tier)).outcome.allowed)
+                    .count()
+            };
+            assert_eq!(allowed(1, Some("free")), 1);
+            assert_eq!(allowed(2, Some("pro")), 3);
+            assert_eq!(allowed(3, None), 2);
+        }
+    }
+
+    #[test]
+    fn test_override() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_limit(1);
+
+        assert!(dur.request(1, IpAndPath::new(None, None)).outcome.allowed);
+        assert!(!dur.request(1, IpAndPath::new(None, None)).outcome.allowed);
+
+        let limit = Override {
+            limit: 3,
+            window_time: 300,
+        };
+        dur.override_limit(1, limit, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(dur.limit_override(1).unwrap(), Some(limit));
+        assert!(dur.request(1, IpAndPath::new(None, None)).outcome.allowed);
+
+        let usage = dur.usage(1, None).unwrap();
+        assert_eq!(usage.rate.limit, 3);
+        assert_eq!(usage.remaining, 0);
+        assert_eq!(usage.requests.unwrap().requests, 3);
+
+        dur.reset(1).unwrap();
+        assert_eq!(dur.usage(1, None).unwrap().requests.unwrap().requests, 0);
+    }
+
+    #[test]
+    fn test_usage_of_states() {
+        for algorithm in [
+            Algorithm::TokenBucket,
+            Algorithm::SlidingWindowCounter,
+            Algorithm::Gcra,
+            Algorithm::FixedWindow,
+        ] {
+            let mut dur = Dur::new(Memory::new(), None);
+            dur.config.set_algorithm(algorithm);
+            dur.config.set_limit(3);
+
+            assert_eq!(dur.usage(1, None).unwrap().remaining, 3, "{}", algorithm);
+            for _ in 0..2 {
+                dur.request(1, IpAndPath::new(None, None));
+            }
+            let usage = dur.usage(1, None).unwrap();
+            assert_eq!(usage.remaining, 1, "{}", algorithm);
+            assert!(usage.requests.is_none());
+
+            dur.request(1, IpAndPath::new(None, None));
+            dur.request(1, IpAndPath::new(None, None));
+            assert_eq!(dur.usage(1, None).unwrap().remaining, 0, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        for algorithm in [
+            Algorithm::SlidingWindowLog,
+            Algorithm::TokenBucket,
+            Algorithm::SlidingWindowCounter,
+            Algorithm::Gcra,
+            Algorithm::FixedWindow,
+        ] {
+            let mut dur = Dur::new(Memory::new(), None);
+            dur.config.set_limit(2);
+            dur.config.set_ip_addr_limit(1);
+            dur.config.set_algorithm(algorithm);
+            dur.config
+                .set_rules(vec![Rule::new("login", 1, 60).with_paths(vec!["/login"])]);
+
+            let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+            let request = || IpAndPath::new(Some(ip), Some("/abc".to_owned()));
+
+            // checking doesn't use up the limit.
+            for _ in 0..3 {
+                assert!(dur.check(1, request()).outcome.allowed);
+            }
+            for _ in 0..3 {
+                let check = dur.check(1, request()).outcome;
+                let outcome = dur.request(1, request()).outcome;
+                assert_eq!(check.allowed, outcome.allowed, "{:?}", algorithm);
+                assert_eq!(check.remaining, outcome.remaining, "{:?}", algorithm);
+            }
+            assert_eq!(dur.check(1, request()).outcome.reason, Some(Reason::Limit));
+
+            // neither the ip addresses nor the rules are counted.
+            let other = IpAddr::from(Ipv4Addr::new(127, 0, 0, 2));
+            let decision = dur.check(2, IpAndPath::new(Some(other), Some("/login".to_owned())));
+            assert!(decision.outcome.allowed);
+            assert!(dur.check(2, IpAndPath::from_ip_addr(ip)).outcome.allowed);
+            let decision = dur.request(2, IpAndPath::new(Some(ip), Some("/login".to_owned())));
+            assert!(decision.outcome.allowed);
+            let decision = dur.check(2, IpAndPath::new(Some(ip), Some("/login".to_owned())));
+            assert_eq!(decision.outcome.reason, Some(Reason::Rule));
+            let decision = dur.check(2, IpAndPath::from_ip_addr(other));
+            assert_eq!(decision.outcome.reason, Some(Reason::UniqueIpAddresses));
+        }
+    }
+
+    #[test]
+    fn test_count_rejected() {
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        let limits = Limits::new(None, Some(Ip::new(vec![ip], 1, 300)));
+
+        for algorithm in [Algorithm::SlidingWindowLog, Algorithm::FixedWindow] {
+            let config = Config::new(Some(2), None, None, None, None, limits.clone());
+            let mut dur = Dur::new(Memory::new(), Some(config));
+            dur.config.set_algorithm(algorithm);
+
+            // the request denied by its ip address still
+            // counts against the limit of the id.
+            assert!(dur.request(1, IpAndPath::from_ip_addr(ip)).outcome.allowed);
+            assert!(!dur.request(1, IpAndPath::from_ip_addr(ip)).outcome.allowed);
+            let outcome = dur.request(1, IpAndPath::new(None, None)).outcome;
+            assert_eq!(outcome.reason, Some(Reason::Limit), "{:?}", algorithm);
+
+            dur.config.set_count_rejected(false);
+            assert!(dur.request(2, IpAndPath::from_ip_addr(ip)).outcome.allowed);
+            for _ in 0..3 {
+                let outcome = dur.request(2, IpAndPath::from_ip_addr(ip)).outcome;
+                assert_eq!(outcome.reason, Some(Reason::IpAddressLimit));
+            }
+            assert!(dur.request(2, IpAndPath::new(None, None)).outcome.allowed);
+            assert!(!dur.request(2, IpAndPath::new(None, None)).outcome.allowed);
+        }
+    }
+
+    #[test]
+    fn test_unique_ip_addresses() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_ip_addr_limit(2);
+
+        for i in 1..=2 {
+            let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, i));
+            assert!(
+                dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                    .outcome
+                    .allowed
+            );
+        }
+
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 3));
+        let outcome = dur.request(12938102, IpAndPath::from_ip_addr(ip)).outcome;
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.reason, Some(Reason::UniqueIpAddresses));
+        assert_eq!(dur.backend.request_count(12938102), 2);
+
+        // the other ids have their own ip addresses.
+        assert!(
+            dur.request(1234, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+        let ip = IpAddr::from(Ipv4Addr::new(127, 0, 0, 1));
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_ipv6_prefix() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_ip_addr_limit(1);
+
+        let ip = "2001:db8::1".parse::<IpAddr>().unwrap();
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+
+        // addresses in the same /64 are the same client.
+        let ip = "2001:db8::ffff:1".parse::<IpAddr>().unwrap();
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+        assert_eq!(dur.backend.unique_ip_address_count(12938102), 1);
+
+        let ip = "2001:db8:0:1::1".parse::<IpAddr>().unwrap();
+        assert!(
+            !dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_ip_ranges() {
+        let range = "10.27.0.0/16".parse().unwrap();
+        let ip = Ip::new(Vec::<IpAddr>::new(), 1, 300).with_ip_ranges(vec![range], false);
+        let config = Config::new(None, None, None, None, None, Limits::new(None, Some(ip)));
+        let dur = Dur::new(Memory::new(), Some(config));
+
+        // every address in the range gets its own limit.
+        let ip = IpAddr::from(Ipv4Addr::new(10, 27, 0, 1));
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+        let outcome = dur.request(12938102, IpAndPath::from_ip_addr(ip)).outcome;
+        assert_eq!(outcome.reason, Some(Reason::IpAddressLimit));
+
+        let ip = IpAddr::from(Ipv4Addr::new(10, 27, 0, 2));
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+
+        // addresses out of the range aren't limited.
+        let ip = IpAddr::from(Ipv4Addr::new(10, 28, 0, 1));
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_shared_ip_ranges() {
+        let range = "10.27.0.0/16".parse().unwrap();
+        let ip = Ip::new(Vec::<IpAddr>::new(), 1, 300).with_ip_ranges(vec![range], true);
+        let config = Config::new(None, None, None, None, None, Limits::new(None, Some(ip)));
+        let mut dur = Dur::new(Memory::new(), Some(config));
+        dur.config.set_algorithm(Algorithm::FixedWindow);
+
+        let ip = IpAddr::from(Ipv4Addr::new(10, 27, 0, 1));
+        assert!(
+            dur.request(12938102, IpAndPath::from_ip_addr(ip))
+                .outcome
+                .allowed
+        );
+
+        // the range shares a single limit.
+        let ip = IpAddr::from(Ipv4Addr::new(10, 27, 0, 2));
+        let outcome = dur.request(12938102, IpAndPath::from_ip_addr(ip)).outcome;
+        assert_eq!(outcome.reason, Some(Reason::IpAddressLimit));
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_algorithm(Algorithm::TokenBucket);
+        dur.config.set_capacity(3);
+        dur.config.set_refill_rate(0.001);
+
+        assert_eq!(dur.limit(), 3);
+        assert_eq!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .remaining,
+            2
+        );
+        assert_eq!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .remaining,
+            1
+        );
+        assert_eq!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .remaining,
+            0
+        );
+        assert!(
+            !dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+
+        // other ids have their own buckets.
+        assert!(
+            dur.request(1234, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_sliding_window_counter() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_algorithm(Algorithm::SlidingWindowCounter);
+        dur.config.set_limit(2);
+
+        assert!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+        assert!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+        assert!(
+            !dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_gcra_retry_after() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_algorithm(Algorithm::Gcra);
+        dur.config.set_limit(2);
+        dur.config.set_window_time(10);
+
+        assert!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+        assert!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+
+        let outcome = dur.request(12938102, IpAndPath::new(None, None)).outcome;
+        assert!(!outcome.allowed);
+        assert!(outcome.retry_after.unwrap() <= Duration::from_secs(5));
+        assert!(outcome.retry_after.unwrap() > Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_leaky_bucket_delay() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_algorithm(Algorithm::LeakyBucket);
+        dur.config.set_limit(1);
+        dur.config.set_window_time(10);
+        dur.config.set_max_queue(1);
+
+        let outcome = dur.request(12938102, IpAndPath::new(None, None)).outcome;
+        assert!(outcome.allowed);
+        assert_eq!(outcome.delay, None);
+
+        let outcome = dur.request(12938102, IpAndPath::new(None, None)).outcome;
+        assert!(outcome.allowed);
+        assert!(outcome.delay.unwrap() > Duration::from_secs(9));
+
+        assert!(
+            !dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_fixed_window() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_algorithm(Algorithm::FixedWindow);
+        dur.config.set_limit(2);
+
+        assert!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+        assert!(
+            dur.request(12938102, IpAndPath::new(None, None))
+                .outcome
+                .allowed
+        );
+
+        let outcome = dur.request(12938102, IpAndPath::new(None, None)).outcome;
+        assert!(!outcome.allowed);
+        assert!(outcome.retry_after.is_some());
+    }
+
+    #[test]
+    fn test_leases() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config
+            .set_concurrency(vec![Concurrency::new("reports", 2)]);
+        assert!(dur
+            .acquire_lease(1, &IpAndPath::new(None, None), "exports")
+            .unwrap()
+            .is_none());
+
+        let first = dur
+            .acquire_lease(1, &IpAndPath::new(None, None), "reports")
+            .unwrap()
+            .unwrap();
+        assert!(first.outcome.allowed);
+        assert_eq!(first.outcome.remaining, 1);
+        assert!(
+            dur.acquire_lease(1, &IpAndPath::new(None, None), "reports")
+                .unwrap()
+                .unwrap()
+                .outcome
+                .allowed
+        );
+
+        let denied = dur
+            .acquire_lease(1, &IpAndPath::new(None, None), "reports")
+            .unwrap()
+            .unwrap();
+        assert_eq!(denied.outcome.reason, Some(Reason::Concurrency));
+        assert!(denied.lease.is_none());
+        // the other ids hold their own leases.
+        assert!(
+            dur.acquire_lease(2, &IpAndPath::new(None, None), "reports")
+                .unwrap()
+                .unwrap()
+                .outcome
+                .allowed
+        );
+        assert_eq!(dur.lease_usage(1).unwrap()[0].held, 2);
+
+        let token = first.lease.unwrap().token;
+        assert!(dur.release_lease(&token).unwrap());
+        assert!(!dur.release_lease(&token).unwrap());
+        assert!(!dur.release_lease("not-a-token").unwrap());
+        assert!(
+            dur.acquire_lease(1, &IpAndPath::new(None, None), "reports")
+                .unwrap()
+                .unwrap()
+                .outcome
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_penalty() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_limit(1);
+        dur.config.set_penalty(Penalty::new(
+            2,
+            Duration::from_secs(3600),
+            Duration::from_secs(900),
+        ));
+
+        assert!(dur.request(1, IpAndPath::new(None, None)).outcome.allowed);
+        for _ in 0..3 {
+            let decision = dur.request(1, IpAndPath::new(None, None));
+            assert_eq!(decision.outcome.reason, Some(Reason::Limit));
+        }
+
+        // the third denial inside the window bans the id.
+        let decision = dur.check(1, IpAndPath::new(None, None));
+        assert_eq!(decision.outcome.reason, Some(Reason::Banned));
+        assert!(decision.outcome.retry_after.unwrap() > Duration::from_secs(890));
+        assert_eq!(dur.ban(1).unwrap().unwrap().offences, 1);
+        assert_eq!(dur.bans().unwrap().len(), 1);
+
+        assert!(dur.lift_ban(1).unwrap());
+        assert_eq!(
+            dur.request(1, IpAndPath::new(None, None)).outcome.reason,
+            Some(Reason::Limit)
+        );
+
+        // a ban that follows a remembered one lasts twice as long.
+        let current_timestamp = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        let ban = Ban {
+            until: current_timestamp.as_millis() as u64,
+            offences: 1,
+        };
+        dur.backend
+            .set_ban(
+                2,
+                ban,
+                current_timestamp,
+                current_timestamp + Duration::from_secs(60),
+            )
+            .unwrap();
+        for _ in 0..4 {
+            dur.request(2, IpAndPath::new(None, None));
+        }
+        let ban = dur.ban(2).unwrap().unwrap();
+        assert_eq!(ban.offences, 2);
+        assert!(ban.until >= (current_timestamp + Duration::from_secs(1800)).as_millis() as u64);
+    }
+
+    #[test]
+    fn test_access_lists() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_limit(1);
+        dur.config
+            .set_allow(AccessList::new().with_ids(["1"]).with_paths(["/health"]));
+        dur.config.set_deny(
+            AccessList::new()
+                .with_ids(["2"])
+                .with_ips(["10.0.0.0/8".parse().unwrap()]),
+        );
+
+        for _ in 0..3 {
+            let decision = dur.request(1, IpAndPath::new(None, None));
+            assert!(decision.outcome.allowed);
+            assert_eq!(decision.outcome.reason, Some(Reason::Allowlisted));
+            assert_eq!(decision.outcome.remaining, 1);
+        }
+        let health = IpAndPath::new(None, Some("/health".to_owned()));
+        assert!(dur.request(3, health.clone()).outcome.allowed);
+        assert!(dur.request(3, health).outcome.allowed);
+
+        // the deny list goes first, nothing is counted either way.
+        let decision = dur.request(2, IpAndPath::new(None, Some("/health".to_owned())));
+        assert_eq!(decision.outcome.reason, Some(Reason::Denylisted));
+        let ip = IpAndPath::from_ip_addr(Ipv4Addr::new(10, 27, 104, 15));
+        assert_eq!(dur.request(1, ip).outcome.reason, Some(Reason::Denylisted));
+        assert!(dur.backend.is_empty());
+
+        assert!(dur.request(3, IpAndPath::new(None, None)).outcome.allowed);
+        assert!(!dur.request(3, IpAndPath::new(None, None)).outcome.allowed);
+    }
+
+    #[test]
+    fn test_leases_access_lists() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config
+            .set_concurrency(vec![Concurrency::new("reports", 1)]);
+        dur.config.set_allow(AccessList::new().with_ids(["1"]));
+        dur.config
+            .set_deny(AccessList::new().with_ips(["10.0.0.0/8".parse().unwrap()]));
+        let anywhere = IpAndPath::new(None, None);
+
+        // allowlisted ids aren't capped.
+        for _ in 0..3 {
+            let leased = dur.acquire_lease(1, &anywhere, "reports").unwrap().unwrap();
+            assert!(leased.outcome.allowed);
+            assert_eq!(leased.outcome.reason, Some(Reason::Allowlisted));
+            assert!(leased.lease.is_some());
+        }
+        assert_eq!(dur.lease_usage(1).unwrap()[0].held, 3);
+
+        let denylisted = IpAndPath::from_ip_addr(Ipv4Addr::new(10, 27, 104, 15));
+        let denied = dur
+            .acquire_lease(2, &denylisted, "reports")
+            .unwrap()
+            .unwrap();
+        assert_eq!(denied.outcome.reason, Some(Reason::Denylisted));
+        assert!(denied.lease.is_none());
+        assert_eq!(dur.lease_usage(2).unwrap()[0].held, 0);
+    }
+
+    #[test]
+    fn test_leases_banned() {
+        let mut dur = Dur::new(Memory::new(), None);
+        dur.config.set_limit(1);
+        dur.config
+            .set_concurrency(vec![Concurrency::new("reports", 5)]);
+        dur.config.set_penalty(Penalty::new(
+            1,
+            Duration::from_secs(3600),
+            Duration::from_secs(900),
+        ));
+
+        for _ in 0..3 {
+            dur.request(1, IpAndPath::new(None, None));
+        }
+        assert!(dur.ban(1).unwrap().is_some());
+
+        let denied = dur
+            .acquire_lease(1, &IpAndPath::new(None, None), "reports")
+            .unwrap()
+            .unwrap();
+        assert_eq!(denied.outcome.reason, Some(Reason::Banned));
+        assert!(denied.outcome.retry_after.is_some());
+        assert!(denied.lease.is_none());
+        assert_eq!(dur.lease_usage(1).unwrap()[0].held, 0);
+    }
 }
@@ -1,3 +1,11 @@
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryInto,
+    hash::{BuildHasher, Hasher},
+    sync::OnceLock,
+    time::SystemTime,
+};
+
 use actix_web::{web::Json, ResponseError};
 use serde::Serialize;
 
@@ -9,3 +17,142 @@ where
 {
     Ok(Json(data))
 }
+
+/// The top bit of the id of a hashed key, numeric
+/// ids are only stored as they are below it.
+const HASHED: u64 = 1 << 63;
+
+/// Hashes keys to the ids they are stored under in the backends,
+/// with SipHash-2-4 keyed by a secret, so keys can't be picked to
+/// collide without knowing it. Every instance that shares a backend
+/// has to be given the same secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyHasher {
+    k0: u64,
+    k1: u64,
+}
+
+impl KeyHasher {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            k0: siphash(0, 0, secret.as_bytes()),
+            k1: siphash(0, 1, secret.as_bytes()),
+        }
+    }
+
+    /// Random keys, the same ones for as long as the process runs.
+    pub fn random() -> Self {
+        static KEYS: OnceLock<KeyHasher> = OnceLock::new();
+
+        *KEYS.get_or_init(|| Self {
+            k0: nonce(),
+            k1: nonce(),
+        })
+    }
+
+    pub fn hash(&self, key: &str) -> u64 {
+        siphash(self.k0, self.k1, key.as_bytes())
+    }
+
+    /// The id a key is stored under in the backend. Numeric keys
+    /// are stored as they are, the others are hashed with the top
+    /// bit set, so a key is never stored under a numeric id.
+    pub fn id_of(&self, key: &str) -> u64 {
+        match key.parse::<u64>() {
+            Ok(id) if id < HASHED && id.to_string() == key => id,
+            _ => self.hash(key) | HASHED,
+        }
+    }
+}
+
+fn siphash(k0: u64, k1: u64, bytes: &[u8]) -> u64 {
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        compress(&mut v, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    // the last block holds the length, and the bytes left over.
+    let last = chunks
+        .remainder()
+        .iter()
+        .enumerate()
+        .fold((bytes.len() as u64) << 56, |last, (i, byte)| {
+            last | (*byte as u64) << (8 * i)
+        });
+    compress(&mut v, last);
+
+    v[2] ^= 0xff;
+    (0..4).for_each(|_| sip_round(&mut v));
+
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn compress(v: &mut [u64; 4], m: u64) {
+    v[3] ^= m;
+    sip_round(v);
+    sip_round(v);
+    v[0] ^= m;
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+/// A random number, used to tell leases apart. Every
+/// `RandomState` is seeded with its own random keys,
+/// which is random enough without another dependency.
+pub fn nonce() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_of() {
+        let hasher = KeyHasher::new("secret");
+
+        assert_eq!(hasher.id_of("1234"), 1234);
+        assert_eq!(hasher.id_of("01234"), hasher.hash("01234") | HASHED);
+        assert_eq!(hasher.id_of("acme"), hasher.hash("acme") | HASHED);
+
+        // numeric ids never share the ids of hashed keys.
+        let id = hasher.id_of("acme");
+        assert_ne!(hasher.id_of(&id.to_string()), id);
+        assert_ne!(hasher.id_of("acme"), KeyHasher::new("other").id_of("acme"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_siphash() {
+        // the std hasher is SipHash-2-4, but deprecated, and
+        // its output isn't promised to stay the same.
+        let message: Vec<u8> = (0..64).collect();
+        for len in 0..message.len() {
+            let mut hasher = std::hash::SipHasher::new_with_keys(1, 2);
+            hasher.write(&message[..len]);
+            assert_eq!(siphash(1, 2, &message[..len]), hasher.finish());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+mod algorithm;
 mod api;
 mod backend;
 mod client;
@@ -5,9 +6,12 @@ mod config;
 mod dur;
 mod helpers;
 
-use std::sync::Mutex;
+use std::{io, thread, time::Duration};
 
-pub use backend::{Backend, IpAndPath, Memory};
+pub use backend::{
+    Backend, BackendKind, Ban, Counts, IpAndPath, Memory, Override, Redis, ShardedMemory, Usage,
+    Windows,
+};
 pub use config::Config;
 
 use actix_web::{web, App, HttpServer};
@@ -18,16 +22,53 @@ pub use dur::Dur;
 async fn main() -> std::io::Result<()> {
     let config = client::cli();
 
-    let data = web::Data::new(Mutex::new(Dur::new(Memory::new(), Some(config.clone()))));
+    let backend: Box<dyn Backend> = match (config.backend(), config.max_keys()) {
+        (BackendKind::Memory, None) => Box::new(Memory::new()),
+        (BackendKind::Memory, Some(max_keys)) => Box::new(Memory::new().with_max_keys(max_keys)),
+        (BackendKind::ShardedMemory, None) => Box::new(ShardedMemory::new(config.shards())),
+        (BackendKind::ShardedMemory, Some(max_keys)) => {
+            Box::new(ShardedMemory::new(config.shards()).with_max_keys(max_keys))
+        }
+        (BackendKind::Redis, _) => Box::new(
+            Redis::open(&config.redis_url())
+                .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why.to_string()))?,
+        ),
+    };
+
+    if config.backend() == BackendKind::Redis && config.hash_secret().is_none() {
+        eprintln!("no hash_secret is set, keys are hashed with a key every instance knows");
+    }
+
+    let data = web::Data::new(Dur::new(backend, Some(config.clone())));
+
+    if config.sweep_interval() > 0 {
+        let dur = data.clone();
+        let interval = Duration::from_secs(config.sweep_interval());
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            dur.sweep();
+        });
+    }
 
     eprintln!("dur is running on: {}", &config.host_and_port());
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(data.clone())
             .service(api::get_health)
             .service(api::new_request)
-    })
-    .bind(&config.host_and_port())?
-    .run()
-    .await
+            .service(api::check_request)
+            .service(api::acquire_lease)
+            .service(api::release_lease)
+            .service(api::get_id)
+            .service(api::delete_id)
+            .service(api::put_limit)
+            .service(api::get_bans)
+            .service(api::delete_ban)
+    });
+
+    if let Some(workers) = config.workers() {
+        server = server.workers(workers);
+    }
+
+    server.bind(config.host_and_port())?.run().await
 }